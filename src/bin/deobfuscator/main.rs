@@ -1,16 +1,16 @@
 use std::env;
-use std::sync::Arc;
-use swc::config::Options;
-use swc_core::common::errors::{ColorConfig, Handler};
-use swc_core::common::{chain, FileName, Globals, GLOBALS, Mark, SourceMap};
-use swc_core::common::comments::SingleThreadedComments;
-use swc_core::ecma::transforms::base::pass::noop;
-use swc_ecma_transforms::optimization::simplify::expr_simplifier;
-use vercel_anti_bot::{decode_data, deobfuscate};
-use swc_core::ecma::visit::as_folder;
+use std::fs;
+use base64::Engine;
+use vercel_anti_bot::decode_data;
+use vercel_anti_bot::deobfuscate::{Deobfuscator, Pass};
 
 // Deobfuscates the script from the given data.
 // This is mainly intended for debug purposes.
+//
+// Pass `--source-map <file>` to write a source map to a file,
+// or `--inline-source-map` to append it to the output.
+// Pass `--report` to print what was deobfuscated as JSON instead of the code.
+// Pass `--rename` to rename mangled identifiers and print what they were renamed to.
 fn main() {
     // Get data
     let args: Vec<String> = env::args().collect();
@@ -22,42 +22,85 @@ fn main() {
             return;
         }
     };
+    // Get options
+    let mut source_map_file = None;
+    let mut inline_source_map = false;
+    let mut print_report = false;
+    let mut rename = false;
+    let mut options = args.iter().skip(2);
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--source-map" => match options.next() {
+                Some(file) => source_map_file = Some(file),
+                None => {
+                    println!("You must pass in the file to write the source map to.");
+                    return;
+                }
+            },
+            "--inline-source-map" => inline_source_map = true,
+            "--report" => print_report = true,
+            "--rename" => rename = true,
+            arg => {
+                println!("Unknown argument: {}", arg);
+                return;
+            }
+        }
+    }
     // Decode challenge
     let challenge = decode_data(data.as_str().trim())
         .expect("failed to decode challenge");
 
-    let cm = Arc::<SourceMap>::default();
-    let handler = Handler::with_tty_emitter(ColorConfig::Auto, true, false, Some(cm.clone()));
-    let c = swc::Compiler::new(cm.clone());
-    let fm = cm.new_source_file(
-        FileName::Custom("usage.js".into()),
-        format!("({})", challenge.code).into()
-    );
+    // You can chain `.input(challenge.input).enable(Pass::MathExpr)` to evaluate
+    // the math expression. This is mainly used for debug purposes.
+    let mut deobfuscator = Deobfuscator::new();
+    if rename {
+        deobfuscator = deobfuscator.enable(Pass::Rename);
+    }
+    if source_map_file.is_some() || inline_source_map {
+        deobfuscator = deobfuscator.emit_source_map("challenge.js");
+    }
+    // Parsed as an expression so the source map points into the challenge's code
+    let output = deobfuscator
+        .deobfuscate_expr(challenge.code)
+        .expect("failed to deobfuscate challenge");
+
+    // Report the obfuscator.io protections that were removed
+    for protection in &output.protections {
+        eprintln!("Removed protection: {:?}", protection);
+    }
 
-    let globals = Globals::new();
-    GLOBALS.set(&globals, || {
-        let output = c.process_js_with_custom_pass(
-            fm,
-            None,
-            &handler,
-            &Options::default(),
-            SingleThreadedComments::default(),
-            |_| noop(),
-            |_| chain!(
-                expr_simplifier(Mark::new(), Default::default()),
-                as_folder(deobfuscate::proxy_vars::Visitor::default()),
-                as_folder(deobfuscate::strings::Visitor),
-                as_folder(deobfuscate::computed_member_expr::Visitor),
+    // Report string calls that were left obfuscated
+    for call in &output.unresolved_calls {
+        let loc = deobfuscator.source_map().lookup_char_pos(call.span.lo);
+        match call.index_error {
+            Some(error) => eprintln!("Couldn't resolve call to {} at {}:{}: {}", call.callee, loc.line, loc.col_display, error),
+            None => eprintln!("Couldn't resolve call to {} at {}:{}", call.callee, loc.line, loc.col_display)
+        }
+    }
 
-                // You can un-comment this line to evaluate the math expression.
-                // Since we don't know the input here, we can only use a dummy input,
-                // unless you specify the input manually.
-                // This is mainly used for debug purposes.
-                //as_folder(deobfuscate::math_expr::Visitor::new(0.2))
-            )
-        )
-            .expect("process_js_with_custom_pass failed");
+    // Report the new names, so they can be looked up in the original code
+    for renamed in &output.renamed {
+        let loc = deobfuscator.source_map().lookup_char_pos(renamed.span.lo);
+        eprintln!("Renamed {} to {} at {}:{}", renamed.old, renamed.new, loc.line, loc.col_display);
+    }
 
-        println!("{}", output.code);
-    });
+    if print_report {
+        let report = serde_json::to_string_pretty(&output.report)
+            .expect("failed to serialize report");
+        println!("{}", report);
+        return;
+    }
+
+    let mut code = output.code;
+    if let Some(source_map) = output.source_map {
+        if let Some(file) = source_map_file {
+            fs::write(file, source_map)
+                .expect("failed to write source map");
+            code.push_str(&format!("//# sourceMappingURL={}\n", file));
+        } else {
+            let encoded = base64::engine::general_purpose::STANDARD.encode(source_map);
+            code.push_str(&format!("//# sourceMappingURL=data:application/json;base64,{}\n", encoded));
+        }
+    }
+    println!("{}", code);
 }
@@ -0,0 +1,1090 @@
+use std::collections::{HashMap, HashSet};
+use swc_core::common::{BytePos, DUMMY_SP, EqIgnoreSpan};
+use swc_core::common::util::take::Take;
+use swc_core::ecma::ast::{BinaryOp, BlockStmtOrExpr, Callee, Decl, Expr, FnDecl, Id, Ident, Lit, MemberProp, ModuleItem, Pat, Program, PropName, Stmt, Str, VarDecl, VarDeclarator, VarDeclKind};
+use swc_core::ecma::atoms::JsWord;
+use swc_core::ecma::visit::{Visit, VisitMut, VisitMutWith, VisitWith};
+use swc_ecma_parser::{EsConfig, Parser, StringInput, Syntax};
+
+/// A protection added by obfuscator.io that [Visitor] removed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Protection {
+    /// `selfDefending`: a regex run on the code's own `toString` that hangs
+    /// if the code was reformatted.
+    SelfDefending,
+
+    /// `debugProtection`: a loop of `debugger` statements while DevTools is open.
+    DebugProtection,
+
+    /// `debugProtectionInterval`: runs the debug protection with `setInterval`.
+    DebugProtectionInterval,
+
+    /// `disableConsoleOutput`: replaces the `console` methods with empty functions.
+    DisableConsoleOutput
+}
+
+/// Removes the code generated by obfuscator.io's `selfDefending`, `debugProtection`
+/// and `disableConsoleOutput` options.
+///
+/// Each protection is a callback passed to a "call controller", for example:
+/// ```js
+/// var b = a(this, function() {
+///     return b.toString().search('(((.+)+)+)+$').toString().constructor(b).search('(((.+)+)+)+$');
+/// });
+/// b();
+/// ```
+///
+/// The call controllers and callbacks are only recognised if they're exactly the code
+/// obfuscator.io generates, apart from names and formatting, so nothing else is removed.
+/// The callbacks are removed along with the calls to them. The call controller and
+/// the debug protection function are removed once nothing else references them.
+#[derive(Default)]
+pub struct Visitor {
+    /// The protections that were removed, in the order they were found.
+    pub protections: Vec<Protection>
+}
+
+impl VisitMut for Visitor {
+    fn visit_mut_program(&mut self, program: &mut Program) {
+        let templates = Templates::new();
+
+        // Find the call controllers, which every protection is passed to
+        let mut controllers_visitor = ControllersVisitor {
+            template: &templates.controller,
+            controllers: HashSet::new()
+        };
+        program.visit_with(&mut controllers_visitor);
+        if controllers_visitor.controllers.is_empty() {
+            return;
+        }
+
+        // Remove the protection callbacks that aren't assigned to variables
+        let mut callbacks_visitor = CallbacksVisitor {
+            templates: &templates,
+            controllers: &controllers_visitor.controllers,
+            callbacks: Vec::new()
+        };
+        program.visit_mut_children_with(&mut callbacks_visitor);
+
+        // Callbacks assigned to variables are only removed if the variables are only called,
+        // so the calls can be removed along with them
+        let mut removed_vars: HashSet<Id> = callbacks_visitor.callbacks
+            .iter()
+            .filter_map(|(var, _)| var.clone())
+            .collect();
+        if !removed_vars.is_empty() {
+            let mut uses_visitor = CallbackUsesVisitor {
+                vars: &removed_vars,
+                other_uses: HashSet::new()
+            };
+            program.visit_mut_children_with(&mut uses_visitor);
+            let other_uses = uses_visitor.other_uses;
+            removed_vars.retain(|id| !other_uses.contains(id));
+        }
+        let mut found = ProtectionMatch::default();
+        for (var, callback) in callbacks_visitor.callbacks {
+            if var.is_none_or(|var| removed_vars.contains(&var)) {
+                found.append(callback);
+            }
+        }
+        if found.protections.is_empty() {
+            return;
+        }
+        let mut remove_vars_visitor = RemoveCallbackVarsVisitor {
+            vars: &removed_vars
+        };
+        program.visit_mut_children_with(&mut remove_vars_visitor);
+
+        // Remove the calls to them, along with debug protection intervals
+        let mut calls_visitor = CallsVisitor {
+            removed_vars: &removed_vars,
+            debug_fns: &found.debug_fns,
+            has_interval: false
+        };
+        program.visit_mut_children_with(&mut calls_visitor);
+        if calls_visitor.has_interval {
+            found.add(Protection::DebugProtectionInterval);
+        }
+
+        // Remove the call controllers and debug protection functions
+        // if nothing else uses them
+        let candidates: HashSet<Id> = found.controllers
+            .union(&found.debug_fns)
+            .cloned()
+            .collect();
+        let mut references_visitor = ReferencesVisitor {
+            candidates: &candidates,
+            references: HashMap::new()
+        };
+        program.visit_mut_children_with(&mut references_visitor);
+        let unused = |ids: &HashSet<Id>| ids
+            .iter()
+            .filter(|id| !references_visitor.references.contains_key(*id))
+            .cloned()
+            .collect();
+        let mut remove_visitor = RemoveDeclsVisitor {
+            controllers: unused(&found.controllers),
+            debug_fns: unused(&found.debug_fns),
+            debug_fn_templates: &templates.debug_fns
+        };
+        program.visit_mut_children_with(&mut remove_visitor);
+
+        for protection in found.protections {
+            if !self.protections.contains(&protection) {
+                self.protections.push(protection);
+            }
+        }
+    }
+}
+
+/// obfuscator.io's call controller, which only runs the callback the first time it's called.
+const CALL_CONTROLLER: &str = r#"
+var callController = function () {
+    var firstCall = true;
+    return function (context, fn) {
+        var rfn = firstCall ? function () {
+            if (fn) {
+                var res = fn.apply(context, arguments);
+                fn = null;
+                return res;
+            }
+        } : function () {};
+        firstCall = false;
+        return rfn;
+    };
+}();
+"#;
+
+/// The `selfDefending` callback.
+const SELF_DEFENDING: &str = r#"
+var selfDefending = callController(this, function () {
+    return selfDefending.toString().search('(((.+)+)+)+$').toString().constructor(selfDefending).search('(((.+)+)+)+$');
+});
+"#;
+
+/// The `debugProtection` callback, which runs in its own function.
+const DEBUG_PROTECTION: &str = r#"
+(function () {
+    callController(this, function () {
+        var regExp1 = new RegExp('function *\\( *\\)');
+        var regExp2 = new RegExp('\\+\\+ *(?:[a-zA-Z_$][0-9a-zA-Z_$]*)', 'i');
+        var result = debugProtection('init');
+        if (!regExp1.test(result + 'chain') || !regExp2.test(result + 'input')) {
+            result('0');
+        } else {
+            debugProtection();
+        }
+    })();
+})();
+"#;
+
+/// The function the `debugProtection` callback calls. `{debugger}` is replaced with
+/// one of [DEBUGGERS].
+const DEBUG_FN: &str = r#"
+function debugProtection(ret) {
+    function debuggerProtection(counter) {
+        {debugger}
+        debuggerProtection(++counter);
+    }
+    try {
+        if (ret) {
+            return debuggerProtection;
+        } else {
+            debuggerProtection(0);
+        }
+    } catch (y) {}
+}
+"#;
+
+/// The ways the debug protection function runs `debugger`, with and without `eval`.
+const DEBUGGERS: [&str; 2] = [
+    r#"
+    if (typeof counter === 'string') {
+        return function (arg) {}.constructor('while (true) {}').apply('counter');
+    } else {
+        if (('' + counter / counter)['length'] !== 1 || counter % 20 === 0) {
+            (function () {
+                return true;
+            }).constructor('debu' + 'gger').call('action');
+        } else {
+            (function () {
+                return false;
+            }).constructor('debu' + 'gger').apply('stateObject');
+        }
+    }
+    "#,
+    r#"
+    if (typeof counter === 'string') {
+        var func = function () {
+            while (true) {}
+        };
+        return func();
+    } else {
+        if (('' + counter / counter)['length'] !== 1 || counter % 20 === 0) {
+            debugger;
+        } else {
+            debugger;
+        }
+    }
+    "#
+];
+
+/// The `disableConsoleOutput` callback. `{global}` is replaced with one of [GLOBAL_OBJECTS].
+const CONSOLE_OUTPUT: &str = r#"
+var consoleOutput = callController(this, function () {
+    {global}
+    var consoleObject = that.console = that.console || {};
+    var methods = ['log', 'warn', 'info', 'error', 'exception', 'table', 'trace'];
+    for (var index = 0; index < methods.length; index++) {
+        var func = callController.constructor.prototype.bind(callController);
+        var methodName = methods[index];
+        var originalFunction = consoleObject[methodName] || func;
+        func['__proto__'] = callController.bind(callController);
+        func.toString = originalFunction.toString.bind(originalFunction);
+        consoleObject[methodName] = func;
+    }
+});
+"#;
+
+/// The ways the `disableConsoleOutput` callback gets the global object as `that`.
+const GLOBAL_OBJECTS: [&str; 3] = [
+    r#"
+    var that;
+    try {
+        var getGlobal = Function('return (function() ' + '{}.constructor("return this")( )' + ');');
+        that = getGlobal();
+    } catch (e) {
+        that = window;
+    }
+    "#,
+    r#"
+    var getGlobal = function () {
+        var globalObject;
+        try {
+            globalObject = Function('return (function() ' + '{}.constructor("return this")( )' + ');')();
+        } catch (e) {
+            globalObject = window;
+        }
+        return globalObject;
+    };
+    var that = getGlobal();
+    "#,
+    r#"
+    var that = typeof window !== 'undefined'
+        ? window
+        : typeof process === 'object' && typeof require === 'function' && typeof global === 'object'
+            ? global
+            : this;
+    "#
+];
+
+/// Globals used by the templates, which have to match by name.
+const TEMPLATE_GLOBALS: [&str; 7] = ["Function", "RegExp", "arguments", "global", "process", "require", "window"];
+
+/// Code generated by obfuscator.io, see [Template::matches].
+struct Template {
+    /// The normalized statements.
+    stmts: Vec<Stmt>,
+
+    /// The names in the template, in the order [Normalizer] found them.
+    names: Vec<JsWord>
+}
+
+impl Template {
+    /// Parses the template `code`.
+    fn new(code: &str) -> Self {
+        let input = StringInput::new(code, BytePos(0), BytePos(code.len() as u32));
+        let mut stmts = Parser::new(Syntax::Es(EsConfig::default()), input, None)
+            .parse_script()
+            .expect("template isn't a valid script")
+            .body;
+        let mut normalizer = Normalizer::default();
+        stmts.visit_mut_with(&mut normalizer);
+
+        Self {
+            stmts,
+            names: normalizer.ids.into_iter().map(|(sym, _)| sym).collect()
+        }
+    }
+
+    /// Matches `stmts` with the template. Formatting, parentheses, strings split with `+`,
+    /// the kind of variable declarations and what variables are named don't matter,
+    /// as long as each variable is used where the template uses the same one.
+    /// Returns the [Id] each name in the template matched.
+    fn matches(&self, mut stmts: Vec<Stmt>) -> Option<HashMap<JsWord, Id>> {
+        let mut normalizer = Normalizer::default();
+        stmts.visit_mut_with(&mut normalizer);
+        if !stmts.eq_ignore_span(&self.stmts) {
+            return None;
+        }
+
+        Some(self.names.iter().cloned().zip(normalizer.ids).collect())
+    }
+}
+
+/// The templates of the code [Visitor] removes.
+struct Templates {
+    /// [CALL_CONTROLLER].
+    controller: Template,
+
+    /// [SELF_DEFENDING].
+    self_defending: Template,
+
+    /// [DEBUG_PROTECTION].
+    debug_protection: Template,
+
+    /// [DEBUG_FN] with each of [DEBUGGERS].
+    debug_fns: Vec<Template>,
+
+    /// [CONSOLE_OUTPUT] with each of [GLOBAL_OBJECTS].
+    console_output: Vec<Template>
+}
+
+impl Templates {
+    fn new() -> Self {
+        Self {
+            controller: Template::new(CALL_CONTROLLER),
+            self_defending: Template::new(SELF_DEFENDING),
+            debug_protection: Template::new(DEBUG_PROTECTION),
+            debug_fns: DEBUGGERS
+                .iter()
+                .map(|debugger| Template::new(&DEBUG_FN.replace("{debugger}", debugger)))
+                .collect(),
+            console_output: GLOBAL_OBJECTS
+                .iter()
+                .map(|global| Template::new(&CONSOLE_OUTPUT.replace("{global}", global)))
+                .collect()
+        }
+    }
+
+    /// Gets the protection `declarator` assigns to a variable, if it's
+    /// a `selfDefending` or `disableConsoleOutput` callback passed to one of `controllers`.
+    fn callback_var(&self, declarator: &VarDeclarator, controllers: &HashSet<Id>) -> Option<ProtectionMatch> {
+        if !declarator.init.as_deref().is_some_and(|init| is_controller_call(init, controllers)) {
+            return None;
+        }
+
+        // The callback is only copied once it's known to be passed to a call controller
+        let stmts = vec![var_stmt(declarator)];
+        let (protection, names) = match self.self_defending.matches(stmts.clone()) {
+            Some(names) => (Protection::SelfDefending, names),
+            None => (
+                Protection::DisableConsoleOutput,
+                self.console_output.iter().find_map(|template| template.matches(stmts.clone()))?
+            )
+        };
+        ProtectionMatch::new(protection, &names, controllers)
+    }
+
+    /// Gets the protection `stmt` runs, if it's the `debugProtection` callback
+    /// passed to one of `controllers`.
+    fn debug_protection(&self, stmt: &Stmt, controllers: &HashSet<Id>) -> Option<ProtectionMatch> {
+        let Stmt::Expr(expr_stmt) = stmt else {
+            return None;
+        };
+        // `(function() { controller(this, function() { ... })(); })()`
+        let Expr::Fn(fn_expr) = unparen(iife_callee(&expr_stmt.expr)?) else {
+            return None;
+        };
+        let [Stmt::Expr(inner)] = fn_expr.function.body.as_ref()?.stmts.as_slice() else {
+            return None;
+        };
+        if !is_controller_call(unparen(iife_callee(&inner.expr)?), controllers) {
+            return None;
+        }
+
+        let names = self.debug_protection.matches(vec![stmt.clone()])?;
+        let mut found = ProtectionMatch::new(Protection::DebugProtection, &names, controllers)?;
+        found.debug_fns.insert(names.get(&JsWord::from("debugProtection"))?.clone());
+        Some(found)
+    }
+}
+
+/// Wraps `declarator` in a variable declaration, so it can be matched with a [Template].
+fn var_stmt(declarator: &VarDeclarator) -> Stmt {
+    Stmt::Decl(Decl::Var(Box::new(VarDecl {
+        span: DUMMY_SP,
+        kind: VarDeclKind::Var,
+        declare: false,
+        decls: vec![declarator.clone()]
+    })))
+}
+
+/// Removes the parentheses around `expr`.
+fn unparen(mut expr: &Expr) -> &Expr {
+    while let Expr::Paren(paren) = expr {
+        expr = &paren.expr;
+    }
+    expr
+}
+
+/// Gets the function `expr` calls without arguments.
+fn iife_callee(expr: &Expr) -> Option<&Expr> {
+    match unparen(expr) {
+        Expr::Call(call) if call.args.is_empty() => call.callee.as_expr().map(|callee| &**callee),
+        _ => None
+    }
+}
+
+/// If `expr` is `controller(this, function() { ... })`, where `controller` is one of `controllers`.
+fn is_controller_call(expr: &Expr, controllers: &HashSet<Id>) -> bool {
+    let Expr::Call(call) = unparen(expr) else {
+        return false;
+    };
+    let is_controller = match call.callee.as_expr().map(|callee| unparen(callee)) {
+        Some(Expr::Ident(id)) => controllers.contains(&id.to_id()),
+        _ => false
+    };
+    is_controller && match call.args.as_slice() {
+        [this, callback] if this.spread.is_none() && callback.spread.is_none() => {
+            matches!(&*this.expr, Expr::This(..)) && matches!(unparen(&callback.expr), Expr::Fn(..))
+        },
+        _ => false
+    }
+}
+
+/// Renames the variables in code so it can be compared with a [Template].
+/// Each variable is named after the order it's first used in, except for globals.
+#[derive(Default)]
+struct Normalizer {
+    /// The variables, in the order they're first used.
+    ids: Vec<Id>,
+
+    /// The index of each variable in `ids`.
+    indexes: HashMap<Id, usize>
+}
+
+impl VisitMut for Normalizer {
+    fn visit_mut_ident(&mut self, ident: &mut Ident) {
+        // Identifiers are compared with their syntax context, which the template doesn't have
+        let id = ident.to_id();
+        ident.span = DUMMY_SP;
+        if TEMPLATE_GLOBALS.contains(&&*ident.sym) {
+            return;
+        }
+        let index = match self.indexes.get(&id) {
+            Some(index) => *index,
+            None => {
+                self.indexes.insert(id.clone(), self.ids.len());
+                self.ids.push(id);
+                self.ids.len() - 1
+            }
+        };
+        ident.sym = JsWord::from(format!("${}", index));
+    }
+
+    // Property names aren't variables, and `obj['prop']` is the same as `obj.prop`
+    fn visit_mut_member_prop(&mut self, prop: &mut MemberProp) {
+        let MemberProp::Computed(computed) = prop else {
+            return;
+        };
+        computed.visit_mut_with(self);
+
+        if let Expr::Lit(Lit::Str(s)) = &*computed.expr {
+            let mut chars = s.value.chars();
+            if chars.next().is_some_and(Ident::is_valid_start) && chars.all(Ident::is_valid_continue) {
+                *prop = MemberProp::Ident(Ident::new(s.value.clone(), s.span));
+            }
+        }
+    }
+
+    fn visit_mut_prop_name(&mut self, _: &mut PropName) {}
+
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        expr.visit_mut_children_with(self);
+
+        match expr {
+            Expr::Paren(paren) => *expr = *paren.expr.take(),
+            // `'debu' + 'gger'`
+            Expr::Bin(bin) if bin.op == BinaryOp::Add => {
+                if let (Expr::Lit(Lit::Str(left)), Expr::Lit(Lit::Str(right))) = (&*bin.left, &*bin.right) {
+                    let value = JsWord::from(format!("{}{}", left.value, right.value));
+                    *expr = Expr::Lit(Lit::Str(Str::from(value)));
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn visit_mut_var_decl(&mut self, var: &mut VarDecl) {
+        var.kind = VarDeclKind::Var;
+        var.visit_mut_children_with(self);
+    }
+}
+
+/// Finds the call controllers.
+struct ControllersVisitor<'a> {
+    /// [CALL_CONTROLLER].
+    template: &'a Template,
+
+    /// The variables holding call controllers.
+    controllers: HashSet<Id>
+}
+
+impl<'a> Visit for ControllersVisitor<'a> {
+    fn visit_var_declarator(&mut self, declarator: &VarDeclarator) {
+        declarator.visit_children_with(self);
+
+        // `var controller = function() { ... }();`
+        let Pat::Ident(id) = &declarator.name else {
+            return;
+        };
+        let is_iife = declarator.init
+            .as_deref()
+            .and_then(iife_callee)
+            .is_some_and(|callee| matches!(unparen(callee), Expr::Fn(..)));
+        if is_iife && self.template.matches(vec![var_stmt(declarator)]).is_some() {
+            self.controllers.insert(id.to_id());
+        }
+    }
+}
+
+/// Protection callbacks, or calls to them.
+#[derive(Default)]
+struct ProtectionMatch {
+    /// The protections the callbacks implement, in the order they were found.
+    protections: Vec<Protection>,
+
+    /// The call controllers the callbacks were passed to.
+    controllers: HashSet<Id>,
+
+    /// The functions called by debug protection callbacks.
+    debug_fns: HashSet<Id>
+}
+
+impl ProtectionMatch {
+    /// Constructs a match for a `protection` callback that matched a template with `names`,
+    /// if it was passed to one of `controllers`.
+    fn new(protection: Protection, names: &HashMap<JsWord, Id>, controllers: &HashSet<Id>) -> Option<Self> {
+        let controller = names.get(&JsWord::from("callController")).filter(|id| controllers.contains(*id))?;
+        Some(Self {
+            protections: vec![protection],
+            controllers: HashSet::from([controller.clone()]),
+            debug_fns: HashSet::new()
+        })
+    }
+
+    /// Records a protection.
+    fn add(&mut self, protection: Protection) {
+        if !self.protections.contains(&protection) {
+            self.protections.push(protection);
+        }
+    }
+
+    /// Adds the protections, call controllers and functions of `other`.
+    fn append(&mut self, other: ProtectionMatch) {
+        for protection in other.protections {
+            self.add(protection);
+        }
+        self.controllers.extend(other.controllers);
+        self.debug_fns.extend(other.debug_fns);
+    }
+}
+
+/// Removes the protection callbacks that are statements, and finds
+/// the ones assigned to variables.
+struct CallbacksVisitor<'a> {
+    /// The templates to match callbacks with.
+    templates: &'a Templates,
+
+    /// The call controllers callbacks are passed to.
+    controllers: &'a HashSet<Id>,
+
+    /// The callbacks found, in order, along with the variable each one is assigned to.
+    /// Only the callbacks that aren't assigned to a variable have been removed.
+    callbacks: Vec<(Option<Id>, ProtectionMatch)>
+}
+
+impl<'a> VisitMut for CallbacksVisitor<'a> {
+    // Protections are checked before visiting children, so the
+    // wrappers around them are removed too
+    fn visit_mut_stmt(&mut self, stmt: &mut Stmt) {
+        if let Some(found) = self.templates.debug_protection(stmt, self.controllers) {
+            self.callbacks.push((None, found));
+            stmt.take();
+            return;
+        }
+
+        stmt.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        if let Pat::Ident(id) = &declarator.name {
+            if let Some(found) = self.templates.callback_var(declarator, self.controllers) {
+                self.callbacks.push((Some(id.to_id()), found));
+                return;
+            }
+        }
+
+        declarator.visit_mut_children_with(self);
+    }
+
+    // Remove empty statements
+    fn visit_mut_stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        stmts.visit_mut_children_with(self);
+
+        stmts.retain(|s| !matches!(s, Stmt::Empty(..)));
+    }
+
+    // Remove empty ModuleItem's
+    fn visit_mut_module_items(&mut self, stmts: &mut Vec<ModuleItem>) {
+        stmts.visit_mut_children_with(self);
+        stmts.retain(|stmt| !matches!(stmt, ModuleItem::Stmt(Stmt::Empty(..))));
+    }
+}
+
+/// Finds variables holding protection callbacks that are used other than by calling them.
+struct CallbackUsesVisitor<'a> {
+    /// The variables holding protection callbacks.
+    vars: &'a HashSet<Id>,
+
+    /// The variables used other than by calling them.
+    other_uses: HashSet<Id>
+}
+
+impl<'a> VisitMut for CallbackUsesVisitor<'a> {
+    fn visit_mut_stmt(&mut self, stmt: &mut Stmt) {
+        // Calls like `b();` are removed along with the callback
+        if let Stmt::Expr(expr_stmt) = stmt {
+            if CallsVisitor::is_call_to(&expr_stmt.expr, self.vars) {
+                return;
+            }
+        }
+
+        stmt.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        // The callback is removed along with its variable,
+        // including its references to the variable
+        if let Pat::Ident(id) = &declarator.name {
+            if self.vars.contains(&id.to_id()) {
+                return;
+            }
+        }
+
+        declarator.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_ident(&mut self, id: &mut Ident) {
+        if self.vars.contains(&id.to_id()) {
+            self.other_uses.insert(id.to_id());
+        }
+    }
+}
+
+/// Removes the variables holding protection callbacks.
+struct RemoveCallbackVarsVisitor<'a> {
+    /// The variables to remove.
+    vars: &'a HashSet<Id>
+}
+
+impl<'a> VisitMut for RemoveCallbackVarsVisitor<'a> {
+    fn visit_mut_var_decl(&mut self, var: &mut VarDecl) {
+        var.decls.retain(|declarator| match &declarator.name {
+            Pat::Ident(id) => !self.vars.contains(&id.to_id()),
+            _ => true
+        });
+
+        var.visit_mut_children_with(self);
+    }
+
+    // Remove empty declarations
+    fn visit_mut_stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        stmts.visit_mut_children_with(self);
+
+        stmts.retain(|s| !is_empty_var(s));
+    }
+
+    // Remove empty ModuleItem's
+    fn visit_mut_module_items(&mut self, stmts: &mut Vec<ModuleItem>) {
+        stmts.visit_mut_children_with(self);
+        stmts.retain(|stmt| !matches!(stmt, ModuleItem::Stmt(s) if is_empty_var(s)));
+    }
+}
+
+/// If `stmt` is a variable declaration without declarators.
+fn is_empty_var(stmt: &Stmt) -> bool {
+    matches!(stmt, Stmt::Decl(Decl::Var(var)) if var.decls.is_empty())
+}
+
+/// Removes the calls to removed callbacks, and intervals that run the debug protection.
+struct CallsVisitor<'a> {
+    /// The variables callbacks were assigned to.
+    removed_vars: &'a HashSet<Id>,
+
+    /// The functions called by debug protection callbacks.
+    debug_fns: &'a HashSet<Id>,
+
+    /// If a debug protection interval was removed.
+    has_interval: bool
+}
+
+impl<'a> CallsVisitor<'a> {
+    /// If `expr` is a call to one of `ids` without arguments.
+    fn is_call_to(expr: &Expr, ids: &HashSet<Id>) -> bool {
+        match expr {
+            Expr::Call(call) if call.args.is_empty() => match &call.callee {
+                Callee::Expr(callee) => matches!(&**callee, Expr::Ident(id) if ids.contains(&id.to_id())),
+                _ => false
+            },
+            _ => false
+        }
+    }
+
+    /// If `expr` is `setInterval(function() { debug(); }, 4000)`.
+    fn is_debug_interval(&self, expr: &Expr) -> bool {
+        let call = match expr {
+            Expr::Call(call) => call,
+            _ => return false
+        };
+        match &call.callee {
+            Callee::Expr(callee) => match &**callee {
+                Expr::Ident(id) if &*id.sym == "setInterval" => {},
+                _ => return false
+            },
+            _ => return false
+        }
+
+        let callback = match call.args.first() {
+            Some(arg) => &*arg.expr,
+            None => return false
+        };
+        let stmts = match callback {
+            Expr::Fn(fn_expr) => match &fn_expr.function.body {
+                Some(body) => &body.stmts,
+                None => return false
+            },
+            Expr::Arrow(arrow) => match &*arrow.body {
+                BlockStmtOrExpr::BlockStmt(body) => &body.stmts,
+                BlockStmtOrExpr::Expr(expr) => return Self::is_call_to(expr, self.debug_fns)
+            },
+            _ => return false
+        };
+        !stmts.is_empty() && stmts.iter().all(|stmt| match stmt {
+            Stmt::Expr(expr_stmt) => Self::is_call_to(&expr_stmt.expr, self.debug_fns),
+            _ => false
+        })
+    }
+}
+
+impl<'a> VisitMut for CallsVisitor<'a> {
+    fn visit_mut_stmt(&mut self, stmt: &mut Stmt) {
+        stmt.visit_mut_children_with(self);
+
+        if let Stmt::Expr(expr_stmt) = stmt {
+            if Self::is_call_to(&expr_stmt.expr, self.removed_vars) {
+                stmt.take();
+            } else if self.is_debug_interval(&expr_stmt.expr) {
+                self.has_interval = true;
+                stmt.take();
+            }
+        }
+    }
+
+    // Remove empty statements
+    fn visit_mut_stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        stmts.visit_mut_children_with(self);
+
+        stmts.retain(|s| !matches!(s, Stmt::Empty(..)));
+    }
+
+    // Remove empty ModuleItem's
+    fn visit_mut_module_items(&mut self, stmts: &mut Vec<ModuleItem>) {
+        stmts.visit_mut_children_with(self);
+        stmts.retain(|stmt| !matches!(stmt, ModuleItem::Stmt(Stmt::Empty(..))));
+    }
+}
+
+/// Counts the references to the candidates for removal, not counting their declarations.
+struct ReferencesVisitor<'a> {
+    /// The call controllers and debug protection functions.
+    candidates: &'a HashSet<Id>,
+
+    /// The number of references to each candidate.
+    references: HashMap<Id, usize>
+}
+
+impl<'a> VisitMut for ReferencesVisitor<'a> {
+    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        // Skip the name
+        declarator.init.visit_mut_with(self);
+    }
+
+    fn visit_mut_fn_decl(&mut self, fn_decl: &mut FnDecl) {
+        // Skip the name
+        fn_decl.function.visit_mut_with(self);
+    }
+
+    fn visit_mut_ident(&mut self, id: &mut Ident) {
+        if self.candidates.contains(&id.to_id()) {
+            *self.references.entry(id.to_id()).or_default() += 1;
+        }
+    }
+}
+
+/// Removes unused call controllers and debug protection functions.
+struct RemoveDeclsVisitor<'a> {
+    /// The call controllers to remove.
+    controllers: HashSet<Id>,
+
+    /// The functions called by debug protection callbacks that can be removed.
+    debug_fns: HashSet<Id>,
+
+    /// The templates the debug protection function matches one of, [DEBUG_FN].
+    debug_fn_templates: &'a [Template]
+}
+
+impl<'a> RemoveDeclsVisitor<'a> {
+    /// If `fn_decl` is the debug protection function.
+    fn is_debug_fn(&self, fn_decl: &FnDecl) -> bool {
+        let stmts = vec![Stmt::Decl(Decl::Fn(fn_decl.clone()))];
+        self.debug_fn_templates.iter().any(|template| template.matches(stmts.clone()).is_some())
+    }
+}
+
+impl<'a> VisitMut for RemoveDeclsVisitor<'a> {
+    fn visit_mut_var_decl(&mut self, var: &mut VarDecl) {
+        var.decls.retain(|declarator| match &declarator.name {
+            Pat::Ident(id) => !self.controllers.contains(&id.to_id()),
+            _ => true
+        });
+    }
+
+    fn visit_mut_stmt(&mut self, stmt: &mut Stmt) {
+        stmt.visit_mut_children_with(self);
+
+        match stmt {
+            Stmt::Decl(Decl::Var(var)) if var.decls.is_empty() => {
+                stmt.take();
+            },
+            // Only remove the function if it's the debug protection function
+            Stmt::Decl(Decl::Fn(fn_decl)) if self.debug_fns.contains(&fn_decl.ident.to_id()) && self.is_debug_fn(fn_decl) => {
+                stmt.take();
+            },
+            _ => {}
+        }
+    }
+
+    // Remove empty statements
+    fn visit_mut_stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        stmts.visit_mut_children_with(self);
+
+        stmts.retain(|s| !matches!(s, Stmt::Empty(..)));
+    }
+
+    // Remove empty ModuleItem's
+    fn visit_mut_module_items(&mut self, stmts: &mut Vec<ModuleItem>) {
+        stmts.visit_mut_children_with(self);
+        stmts.retain(|stmt| !matches!(stmt, ModuleItem::Stmt(Stmt::Empty(..))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Protection;
+    use crate::deobfuscate::{run_passes, Pass};
+
+    /// obfuscator.io's call controller.
+    const CONTROLLER: &str = r#"var b = function() {
+    var c = true;
+    return function(d, e) {
+        var f = c ? function() {
+            if (e) {
+                var g = e.apply(d, arguments);
+                e = null;
+                return g;
+            }
+        } : function() {};
+        c = false;
+        return f;
+    };
+}();
+"#;
+
+    /// A function protected by obfuscator.io's `selfDefending`, `debugProtection`,
+    /// `debugProtectionInterval` and `disableConsoleOutput` options.
+    const PROTECTED: &str = r#"(function(a) {
+    var b = (function() {
+        var c = true;
+        return function(d, e) {
+            var f = c ? function() {
+                if (e) {
+                    var g = e.apply(d, arguments);
+                    e = null;
+                    return g;
+                }
+            } : function() {};
+            c = false;
+            return f;
+        };
+    }());
+    var h = b(this, function() {
+        return h.toString().search('(((.+)+)+)+$').toString().constructor(h).search('(((.+)+)+)+$');
+    });
+    h();
+    (function() {
+        b(this, function() {
+            var i = new RegExp('function *\\( *\\)');
+            var j = new RegExp('\\+\\+ *(?:[a-zA-Z_$][0-9a-zA-Z_$]*)', 'i');
+            var k = l('init');
+            if (!i.test(k + 'chain') || !j.test(k + 'input')) {
+                k('0');
+            } else {
+                l();
+            }
+        })();
+    }());
+    var m = b(this, function() {
+        var n;
+        try {
+            var o = Function('return (function() ' + '{}.constructor("return this")( )' + ');');
+            n = o();
+        } catch (p) {
+            n = window;
+        }
+        var q = n['console'] = n['console'] || {};
+        var r = ['log', 'warn', 'info', 'error', 'exception', 'table', 'trace'];
+        for (var s = 0; s < r['length']; s++) {
+            var t = b['constructor']['prototype']['bind'](b);
+            var u = r[s];
+            var v = q[u] || t;
+            t['__proto__'] = b['bind'](b);
+            t['toString'] = v['toString']['bind'](v);
+            q[u] = t;
+        }
+    });
+    m();
+    setInterval(function() {
+        l();
+    }, 4000);
+    function l(w) {
+        function x(y) {
+            if (typeof y === 'string') {
+                return function(z) {}.constructor('while (true) {}').apply('counter');
+            } else {
+                if (('' + y / y)['length'] !== 1 || y % 20 === 0) {
+                    (function() {
+                        return true;
+                    }).constructor('debu' + 'gger').call('action');
+                } else {
+                    (function() {
+                        return false;
+                    }).constructor('debu' + 'gger').apply('stateObject');
+                }
+            }
+            x(++y);
+        }
+        try {
+            if (w) {
+                return x;
+            } else {
+                x(0);
+            }
+        } catch (A) {}
+    }
+    return a + 1;
+});"#;
+
+    #[test]
+    fn test_remove_protections() {
+        let output = run_passes(PROTECTED, &[Pass::Resolver, Pass::AntiAnalysis]);
+
+        assert_eq!(output.code, "(function(a) {\n    return a + 1;\n});\n");
+        assert_eq!(output.protections, vec![
+            Protection::SelfDefending,
+            Protection::DebugProtection,
+            Protection::DisableConsoleOutput,
+            Protection::DebugProtectionInterval
+        ]);
+    }
+
+    #[test]
+    fn test_no_eval_variants() {
+        // The global object and `debugger` without `eval`
+        let code = PROTECTED
+            .replace(r#"var n;
+        try {
+            var o = Function('return (function() ' + '{}.constructor("return this")( )' + ');');
+            n = o();
+        } catch (p) {
+            n = window;
+        }"#, "var n = typeof window !== 'undefined' ? window : (typeof process === 'object' && typeof require === 'function' && typeof global === 'object') ? global : this;")
+            .replace("return function(z) {}.constructor('while (true) {}').apply('counter');", "var z = function() { while (true) {} }; return z();")
+            .replace(r#"(function() {
+                        return true;
+                    }).constructor('debu' + 'gger').call('action');"#, "debugger;")
+            .replace(r#"(function() {
+                        return false;
+                    }).constructor('debu' + 'gger').apply('stateObject');"#, "debugger;");
+        assert!(!code.contains("Function(") && !code.contains("'debu'"));
+        let output = run_passes(&code, &[Pass::Resolver, Pass::AntiAnalysis]);
+
+        assert_eq!(output.code, "(function(a) {\n    return a + 1;\n});\n");
+        assert_eq!(output.protections.len(), 4);
+    }
+
+    #[test]
+    fn test_default_passes() {
+        // The passes before this one simplify the generated code
+        let output = run_passes(PROTECTED, &Pass::DEFAULT);
+
+        assert_eq!(output.code, "(function(a) {\n    return a + 1;\n});\n");
+        assert_eq!(output.protections.len(), 4);
+    }
+
+    #[test]
+    fn test_partial_match() {
+        // The callback does more than the protection, so it's kept
+        let code = format!(r#"{}var h = b(this, function() {{
+    console.log(h);
+    return h.toString().search('(((.+)+)+)+$').toString().constructor(h).search('(((.+)+)+)+$');
+}});
+h();
+"#, CONTROLLER);
+        let output = run_passes(&code, &[Pass::Resolver, Pass::AntiAnalysis]);
+
+        assert_eq!(output.code, code);
+        assert!(output.protections.is_empty());
+
+        // `b` isn't a call controller, so it could do anything with the callback
+        let code = r#"var b = function() {
+    return function(d, e) {
+        return e;
+    };
+}();
+var h = b(this, function() {
+    return h.toString().search('(((.+)+)+)+$').toString().constructor(h).search('(((.+)+)+)+$');
+});
+h();
+"#;
+        let output = run_passes(code, &[Pass::Resolver, Pass::AntiAnalysis]);
+
+        assert_eq!(output.code, code);
+        assert!(output.protections.is_empty());
+    }
+
+    #[test]
+    fn test_used_callback() {
+        // `h` is passed on, so it's kept along with its call
+        let code = format!(r#"{}var h = b(this, function() {{
+    return h.toString().search('(((.+)+)+)+$').toString().constructor(h).search('(((.+)+)+)+$');
+}});
+h();
+f(h);
+"#, CONTROLLER);
+        let output = run_passes(&code, &[Pass::Resolver, Pass::AntiAnalysis]);
+
+        assert_eq!(output.code, code);
+        assert!(output.protections.is_empty());
+    }
+
+    #[test]
+    fn test_unprotected() {
+        let code = format!("{}var h = b(this, function() {{\n    return 1;\n}});\n", CONTROLLER);
+        let output = run_passes(&code, &[Pass::Resolver, Pass::AntiAnalysis]);
+
+        assert_eq!(output.code, code);
+        assert!(output.protections.is_empty());
+    }
+}
@@ -1,20 +1,270 @@
-use swc_core::ecma::ast::{Expr, Ident, Lit, MemberExpr, MemberProp};
+use swc_core::ecma::ast::{ClassMember, EsVersion, Expr, Ident, IdentExt, Lit, MemberExpr, MemberProp, Number, ObjectPatProp, Prop, PropName, Str, SuperProp, SuperPropExpr};
 use swc_core::ecma::visit::{VisitMut, VisitMutWith};
 
-/// Replaces computed member properties with identifiers.
-pub struct Visitor;
+/// Replaces computed member properties and keys with identifiers.
+///
+/// `obj["str"]` becomes `obj.str` and `{ ["str"]: 1 }` becomes `{ str: 1 }`
+/// if `str` is a valid identifier name, and numeric strings like `obj["1"]`
+/// become numbers. Other keys are left as strings.
+pub struct Visitor {
+    /// The ES version the output targets. Reserved words can't be
+    /// property names before ES5, so they're left as strings.
+    target: EsVersion,
+
+    /// The members and keys that were changed, in the order they're visited.
+    pub normalised: Vec<NormalisedMember>
+}
+
+/// A member or key that [Visitor] changed.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NormalisedMember {
+    /// The property name.
+    pub key: String,
+
+    /// What the property name became.
+    pub kind: KeyKind
+}
+
+/// What a member or key was changed to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyKind {
+    /// An identifier, like `obj.key`.
+    Identifier,
+
+    /// A number, like `obj[1]`.
+    Number,
+
+    /// A string key that is no longer computed, like `{ "a b": 1 }`.
+    String
+}
+
+impl Default for Visitor {
+    fn default() -> Self {
+        Self::new(EsVersion::latest())
+    }
+}
+
+impl Visitor {
+    /// Constructs a new [Visitor] for output targeting `target`.
+    pub fn new(target: EsVersion) -> Self {
+        Self {
+            target,
+            normalised: Vec::new()
+        }
+    }
+
+    /// If `name` can be used as a property name without quotes.
+    fn is_identifier_name(&self, name: &str) -> bool {
+        let mut chars = name.chars();
+        let is_valid = chars.next().is_some_and(Ident::is_valid_start) && chars.all(Ident::is_valid_continue);
+
+        is_valid && (self.target > EsVersion::Es3 || !(name.is_reserved() || name.is_reserved_in_es3()))
+    }
+
+    /// Returns the key for the string `s`: an identifier if possible,
+    /// otherwise a number if `s` is a number's canonical form, otherwise a string.
+    fn str_key(&self, s: &Str) -> PropName {
+        if self.is_identifier_name(&s.value) {
+            PropName::Ident(Ident::new(s.value.clone(), s.span))
+        } else if let Some(value) = canonical_number(&s.value) {
+            PropName::Num(Number {
+                span: s.span,
+                value,
+                raw: None
+            })
+        } else {
+            PropName::Str(s.clone())
+        }
+    }
+
+    /// Records that the property `key` became `kind`.
+    fn add_normalised(&mut self, key: &str, kind: KeyKind) {
+        self.normalised.push(NormalisedMember {
+            key: key.to_string(),
+            kind
+        });
+    }
+
+    /// Normalises a key. Computed keys named in `special` are left alone,
+    /// since they mean something else when they aren't computed.
+    fn normalize_key(&mut self, key: &mut PropName, special: &[&str]) {
+        let (name, new_key) = match key {
+            PropName::Str(s) => (s.value.to_string(), self.str_key(s)),
+            PropName::Computed(computed) => match &*computed.expr {
+                Expr::Lit(Lit::Str(s)) if !special.contains(&&*s.value) => (s.value.to_string(), self.str_key(s)),
+                Expr::Lit(Lit::Num(n)) if n.value.is_finite() && n.value.is_sign_positive() => {
+                    (n.value.to_string(), PropName::Num(n.clone()))
+                },
+                _ => return
+            },
+            _ => return
+        };
+        let kind = match &new_key {
+            PropName::Ident(..) => KeyKind::Identifier,
+            PropName::Num(..) => KeyKind::Number,
+            // Already a string key
+            PropName::Str(..) if matches!(key, PropName::Str(..)) => return,
+            _ => KeyKind::String
+        };
+        self.add_normalised(&name, kind);
+        *key = new_key;
+    }
+}
+
+/// Returns the number `s` is the canonical string of, like `"1"` or `"0.5"` but not `"01"`,
+/// so that `obj[n]` and `obj[s]` access the same property.
+fn canonical_number(s: &str) -> Option<f64> {
+    let value: f64 = s.parse().ok()?;
+
+    // The range where JS prints numbers without an exponent, like Rust does.
+    // 0 is checked separately so "-0" isn't accepted.
+    let in_range = value == 0.0 || (1e-6..1e21).contains(&value);
+    if in_range && value.to_string() == s {
+        Some(value)
+    } else {
+        None
+    }
+}
 
 impl VisitMut for Visitor {
     fn visit_mut_member_expr(&mut self, member_expr: &mut MemberExpr) {
         member_expr.visit_mut_children_with(self);
 
-        if let MemberProp::Computed(property) = &member_expr.prop {
+        if let MemberProp::Computed(property) = &mut member_expr.prop {
+            if let Expr::Lit(Lit::Str(s)) = &*property.expr {
+                if self.is_identifier_name(&s.value) {
+                    self.add_normalised(&s.value, KeyKind::Identifier);
+                    member_expr.prop = MemberProp::Ident(Ident::new(
+                        s.value.clone(),
+                        property.span
+                    ));
+                } else if let Some(value) = canonical_number(&s.value) {
+                    self.add_normalised(&s.value, KeyKind::Number);
+                    *property.expr = Expr::Lit(Lit::Num(Number {
+                        span: s.span,
+                        value,
+                        raw: None
+                    }));
+                }
+            }
+        }
+    }
+
+    fn visit_mut_super_prop_expr(&mut self, super_prop: &mut SuperPropExpr) {
+        super_prop.visit_mut_children_with(self);
+
+        if let SuperProp::Computed(property) = &super_prop.prop {
             if let Expr::Lit(Lit::Str(s)) = &*property.expr {
-                member_expr.prop = MemberProp::Ident(Ident::new(
-                    s.value.clone(),
-                    property.span
-                ));
+                if self.is_identifier_name(&s.value) {
+                    self.add_normalised(&s.value, KeyKind::Identifier);
+                    super_prop.prop = SuperProp::Ident(Ident::new(
+                        s.value.clone(),
+                        property.span
+                    ));
+                }
             }
         }
     }
+
+    fn visit_mut_prop(&mut self, prop: &mut Prop) {
+        prop.visit_mut_children_with(self);
+
+        let key = match prop {
+            Prop::KeyValue(prop) => &mut prop.key,
+            Prop::Getter(prop) => &mut prop.key,
+            Prop::Setter(prop) => &mut prop.key,
+            Prop::Method(prop) => &mut prop.key,
+            _ => return
+        };
+        // `{ __proto__: v }` sets the prototype, but `{ ["__proto__"]: v }` doesn't
+        self.normalize_key(key, &["__proto__"]);
+    }
+
+    fn visit_mut_class_member(&mut self, member: &mut ClassMember) {
+        member.visit_mut_children_with(self);
+
+        let key = match member {
+            ClassMember::Method(method) => &mut method.key,
+            ClassMember::ClassProp(prop) => &mut prop.key,
+            _ => return
+        };
+        // A method named `constructor` is the class' constructor,
+        // and a static member named `prototype` is an error
+        self.normalize_key(key, &["constructor", "prototype"]);
+    }
+
+    fn visit_mut_object_pat_prop(&mut self, prop: &mut ObjectPatProp) {
+        prop.visit_mut_children_with(self);
+
+        if let ObjectPatProp::KeyValue(prop) = prop {
+            self.normalize_key(&mut prop.key, &[]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use swc_core::ecma::ast::EsVersion;
+    use crate::deobfuscate::{Deobfuscator, Pass};
+
+    fn deobfuscate(code: &str, target: EsVersion) -> String {
+        Deobfuscator::new()
+            .passes(&[Pass::ComputedMemberExpr])
+            .target(target)
+            .deobfuscate(code.to_string())
+            .expect("deobfuscate failed")
+            .code
+    }
+
+    #[test]
+    fn test_member_expr() {
+        let code = r#"a["foo"]["foo-bar"]["1x"]["1"]["01"]["0.5"]["default"]["$_ü"][""];"#;
+        assert_eq!(
+            deobfuscate(code, EsVersion::latest()),
+            "a.foo[\"foo-bar\"][\"1x\"][1][\"01\"][0.5].default.$_ü[\"\"];\n"
+        );
+        assert_eq!(
+            deobfuscate(code, EsVersion::Es3),
+            "a.foo[\"foo-bar\"][\"1x\"][1][\"01\"][0.5][\"default\"].$_ü[\"\"];\n"
+        );
+    }
+
+    #[test]
+    fn test_keys() {
+        let code = r#"var o = {
+    ["foo"]: 1,
+    "bar": 2,
+    ["a b"]: 3,
+    ["2"]: 4,
+    [3]: 5,
+    ["__proto__"]: null,
+    get ["class"]() {}
+};
+class C {
+    ["constructor"]() {}
+    ["method"]() {}
+    static ["prototype"] = 1;
+    ["1.5"] = 2;
+}
+var { ["x"]: x } = o;
+"#;
+        assert_eq!(deobfuscate(code, EsVersion::latest()), r#"var o = {
+    foo: 1,
+    bar: 2,
+    "a b": 3,
+    2: 4,
+    3: 5,
+    ["__proto__"]: null,
+    get class () {}
+};
+class C {
+    ["constructor"]() {}
+    method() {}
+    static ["prototype"] = 1;
+    1.5 = 2;
+}
+var { x: x } = o;
+"#);
+    }
 }
@@ -0,0 +1,525 @@
+use std::collections::HashMap;
+use swc_core::ecma::ast::{ArrayLit, ArrowExpr, Callee, Decl, Expr, Function, Id, Ident, Lit, MemberProp, Program, Stmt, SwitchCase, SwitchStmt, UnaryOp, UpdateOp, VarDeclarator, VarDeclKind};
+use swc_core::ecma::atoms::JsWord;
+use swc_core::ecma::visit::{Visit, VisitMut, VisitMutWith, VisitWith};
+
+/// Rebuilds code flattened by obfuscator.io's `controlFlowFlattening` option.
+///
+/// Example:
+/// ```js
+/// var order = '2|0|1'.split('|'), i = 0;
+/// while (true) {
+///     switch (order[i++]) {
+///         case '0':
+///             b();
+///             continue;
+///         case '1':
+///             c();
+///             continue;
+///         case '2':
+///             a();
+///             continue;
+///     }
+///     break;
+/// }
+/// ```
+///
+/// is replaced with:
+///
+/// ```js
+/// a();
+/// b();
+/// c();
+/// ```
+///
+/// Dispatchers that can't be proven to run every case once, in order,
+/// are left untouched.
+#[derive(Default)]
+pub struct Visitor {
+    /// The references to each identifier in the functions the visitor is inside,
+    /// innermost last. The program counts as the outermost function.
+    /// This is only used internally.
+    references: Vec<HashMap<Id, usize>>
+}
+
+/// Counts the references to each identifier in `node`.
+fn count_references<N: VisitWith<ReferenceVisitor>>(node: &N) -> HashMap<Id, usize> {
+    let mut visitor = ReferenceVisitor::default();
+    node.visit_children_with(&mut visitor);
+    visitor.counts
+}
+
+impl VisitMut for Visitor {
+    fn visit_mut_program(&mut self, program: &mut Program) {
+        self.references.push(count_references(program));
+        program.visit_mut_children_with(self);
+        self.references.pop();
+    }
+
+    // The order and counter are function scoped, so closures anywhere
+    // in the function can use them
+    fn visit_mut_function(&mut self, function: &mut Function) {
+        self.references.push(count_references(function));
+        function.visit_mut_children_with(self);
+        self.references.pop();
+    }
+
+    fn visit_mut_arrow_expr(&mut self, arrow: &mut ArrowExpr) {
+        self.references.push(count_references(arrow));
+        arrow.visit_mut_children_with(self);
+        self.references.pop();
+    }
+
+    fn visit_mut_stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        stmts.visit_mut_children_with(self);
+
+        let Some(references) = self.references.last() else {
+            return;
+        };
+        // Each rebuild changes the indexes, so rebuild one dispatcher at a time
+        while let Some(dispatcher) = find_dispatcher(stmts, references) {
+            rebuild(stmts, dispatcher);
+        }
+    }
+}
+
+/// A value the dispatcher switches on, compared with `===`.
+#[derive(Debug, Clone, PartialEq)]
+enum Key {
+    Str(JsWord),
+    Num(f64)
+}
+
+impl Key {
+    /// Gets the key for a literal.
+    fn from_expr(expr: &Expr) -> Option<Self> {
+        match expr {
+            Expr::Lit(Lit::Str(s)) => Some(Key::Str(s.value.clone())),
+            Expr::Lit(Lit::Num(n)) => Some(Key::Num(n.value)),
+            _ => None
+        }
+    }
+}
+
+/// A flattened block found by [find_dispatcher].
+struct Dispatcher {
+    /// The index of the statement declaring the order.
+    order_stmt: usize,
+
+    /// The [Id] of the order variable.
+    order_id: Id,
+
+    /// The index of the statement declaring the counter.
+    counter_stmt: usize,
+
+    /// The [Id] of the counter variable.
+    counter_id: Id,
+
+    /// The index of the loop.
+    loop_stmt: usize,
+
+    /// The statements of each case, in the order they run.
+    stmts: Vec<Stmt>
+}
+
+/// Finds the first dispatcher in `stmts` that can be rebuilt, where `references`
+/// are the references to each identifier in the enclosing function.
+fn find_dispatcher(stmts: &[Stmt], references: &HashMap<Id, usize>) -> Option<Dispatcher> {
+    stmts
+        .iter()
+        .enumerate()
+        .find_map(|(i, stmt)| get_dispatcher(stmts, i, stmt, references))
+}
+
+/// Gets the [Dispatcher] if `stmt`, at `loop_stmt` in `stmts`, is a dispatcher loop.
+fn get_dispatcher(stmts: &[Stmt], loop_stmt: usize, stmt: &Stmt, references: &HashMap<Id, usize>) -> Option<Dispatcher> {
+    let switch = get_switch(stmt)?;
+    let (order_ident, counter_ident) = get_discriminant(&switch.discriminant)?;
+    let order_id = order_ident.to_id();
+    let counter_id = counter_ident.to_id();
+    if order_id == counter_id {
+        return None;
+    }
+
+    // Find the declarations of the order and counter before the loop
+    let (order_stmt, order_init) = find_declarator(&stmts[..loop_stmt], &order_id)?;
+    let order = get_order(order_init?)?;
+    let (counter_stmt, counter_init) = find_declarator(&stmts[..loop_stmt], &counter_id)?;
+    match counter_init.map(Key::from_expr) {
+        Some(Some(Key::Num(0.0))) => {},
+        _ => return None
+    }
+
+    // The variables can't be used anywhere else in the function, otherwise
+    // the order could be changed while running
+    let count = |id: &Id| references.get(id).copied().unwrap_or(0);
+    // Declaration and the discriminant
+    if count(&order_id) != 2 || count(&counter_id) != 2 {
+        return None;
+    }
+
+    let cases = get_cases(&switch.cases)?;
+    // Every case must run exactly once
+    if order.len() != cases.len() {
+        return None;
+    }
+    let mut rebuilt = Vec::new();
+    let mut used = vec![false; cases.len()];
+    for key in &order {
+        let i = cases.iter().position(|(case_key, _)| case_key == key)?;
+        if used[i] {
+            return None;
+        }
+        used[i] = true;
+        rebuilt.extend(cases[i].1.iter().cloned());
+    }
+
+    Some(Dispatcher {
+        order_stmt,
+        order_id,
+        counter_stmt,
+        counter_id,
+        loop_stmt,
+        stmts: rebuilt
+    })
+}
+
+/// Gets the switch statement from a `while (true) { switch (...) { ... } break; }` loop.
+fn get_switch(stmt: &Stmt) -> Option<&SwitchStmt> {
+    let body = match stmt {
+        Stmt::While(while_stmt) if is_true(&while_stmt.test) => &*while_stmt.body,
+        Stmt::For(for_stmt) if for_stmt.init.is_none() && for_stmt.test.is_none() && for_stmt.update.is_none() => {
+            &*for_stmt.body
+        },
+        _ => return None
+    };
+    let block = match body {
+        Stmt::Block(block) => block,
+        _ => return None
+    };
+
+    match block.stmts.as_slice() {
+        [Stmt::Switch(switch), Stmt::Break(break_stmt)] if break_stmt.label.is_none() => Some(switch),
+        _ => None
+    }
+}
+
+/// If `expr` is always `true`, either `true` or `!![]`.
+fn is_true(expr: &Expr) -> bool {
+    match expr {
+        Expr::Lit(Lit::Bool(b)) => b.value,
+        Expr::Unary(unary) if unary.op == UnaryOp::Bang => match &*unary.arg {
+            Expr::Unary(inner) if inner.op == UnaryOp::Bang => matches!(&*inner.arg, Expr::Array(..)),
+            _ => false
+        },
+        _ => false
+    }
+}
+
+/// Gets the order and counter identifiers from `order[counter++]`.
+fn get_discriminant(expr: &Expr) -> Option<(&Ident, &Ident)> {
+    let member = match expr {
+        Expr::Member(member) => member,
+        _ => return None
+    };
+    let order = match &*member.obj {
+        Expr::Ident(id) => id,
+        _ => return None
+    };
+    let update = match &member.prop {
+        MemberProp::Computed(prop) => match &*prop.expr {
+            Expr::Update(update) => update,
+            _ => return None
+        },
+        _ => return None
+    };
+    if update.op != UpdateOp::PlusPlus || update.prefix {
+        return None;
+    }
+    match &*update.arg {
+        Expr::Ident(counter) => Some((order, counter)),
+        _ => None
+    }
+}
+
+/// Finds the declarator for `id` in `stmts`, returning the index of its statement and its init.
+fn find_declarator<'a>(stmts: &'a [Stmt], id: &Id) -> Option<(usize, Option<&'a Expr>)> {
+    stmts.iter().enumerate().find_map(|(i, stmt)| match stmt {
+        Stmt::Decl(Decl::Var(var)) => var.decls
+            .iter()
+            .find(|declarator| declarator.name.as_ident().map(|name| name.to_id()).as_ref() == Some(id))
+            .map(|declarator| (i, declarator.init.as_deref())),
+        _ => None
+    })
+}
+
+/// Gets the order from either `'1|0|2'.split('|')` or `['1', '0', '2']`.
+fn get_order(expr: &Expr) -> Option<Vec<Key>> {
+    match expr {
+        Expr::Array(array) => get_array_order(array),
+        Expr::Call(call) => {
+            let member = match &call.callee {
+                Callee::Expr(callee) => match &**callee {
+                    Expr::Member(member) => member,
+                    _ => return None
+                },
+                _ => return None
+            };
+            let is_split = match &member.prop {
+                MemberProp::Ident(prop) => &*prop.sym == "split",
+                MemberProp::Computed(prop) => matches!(&*prop.expr, Expr::Lit(Lit::Str(s)) if &*s.value == "split"),
+                _ => false
+            };
+            if !is_split || call.args.len() != 1 || call.args[0].spread.is_some() {
+                return None;
+            }
+
+            let (s, separator) = match (&*member.obj, &*call.args[0].expr) {
+                (Expr::Lit(Lit::Str(s)), Expr::Lit(Lit::Str(separator))) => (&s.value, &separator.value),
+                _ => return None
+            };
+            // An empty separator splits every character
+            if separator.is_empty() {
+                return Some(s.chars().map(|c| Key::Str(JsWord::from(c.to_string()))).collect());
+            }
+            Some(s.split(&**separator).map(|key| Key::Str(JsWord::from(key))).collect())
+        },
+        _ => None
+    }
+}
+
+/// Gets the order from an array of literals.
+fn get_array_order(array: &ArrayLit) -> Option<Vec<Key>> {
+    array.elems
+        .iter()
+        .map(|elem| match elem {
+            Some(elem) if elem.spread.is_none() => Key::from_expr(&elem.expr),
+            _ => None
+        })
+        .collect()
+}
+
+/// Gets the key and statements of each case, without the trailing `continue`.
+/// Returns `None` if a case can't be moved out of the loop.
+fn get_cases(cases: &[SwitchCase]) -> Option<Vec<(Key, &[Stmt])>> {
+    cases
+        .iter()
+        .map(|case| {
+            // Default cases aren't generated
+            let key = Key::from_expr(case.test.as_deref()?)?;
+
+            // Each case must end with a `continue`, or leave the function
+            let stmts = match case.cons.split_last() {
+                Some((Stmt::Continue(continue_stmt), stmts)) if continue_stmt.label.is_none() => stmts,
+                Some((Stmt::Return(..) | Stmt::Throw(..), _)) => &case.cons[..],
+                _ => return None
+            };
+
+            // Lexical declarations would be scoped differently outside of the switch,
+            // and other jumps would target the loop
+            if stmts.iter().any(|stmt| is_lexical_decl(stmt) || has_loop_jump(stmt)) {
+                return None;
+            }
+
+            Some((key, stmts))
+        })
+        .collect()
+}
+
+/// If `stmt` declares a `let`, `const` or class.
+fn is_lexical_decl(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Decl(Decl::Var(var)) => var.kind != VarDeclKind::Var,
+        Stmt::Decl(Decl::Class(..)) => true,
+        _ => false
+    }
+}
+
+/// If `stmt` contains an unlabeled `break` or `continue` that targets the dispatcher loop.
+fn has_loop_jump(stmt: &Stmt) -> bool {
+    let mut visitor = LoopJumpVisitor::default();
+    stmt.visit_with(&mut visitor);
+    visitor.found
+}
+
+/// Replaces the dispatcher with its statements, and removes the order and counter.
+fn rebuild(stmts: &mut Vec<Stmt>, dispatcher: Dispatcher) {
+    let Dispatcher { order_stmt, order_id, counter_stmt, counter_id, loop_stmt, stmts: rebuilt } = dispatcher;
+
+    // Replace the loop first as the declarations come before it
+    stmts.splice(loop_stmt..=loop_stmt, rebuilt);
+
+    // Remove the declarators, and their statement if it becomes empty
+    let mut empty = Vec::new();
+    for i in [order_stmt, counter_stmt] {
+        if let Stmt::Decl(Decl::Var(var)) = &mut stmts[i] {
+            var.decls.retain(|declarator: &VarDeclarator| match declarator.name.as_ident() {
+                Some(name) => name.to_id() != order_id && name.to_id() != counter_id,
+                None => true
+            });
+            if var.decls.is_empty() && !empty.contains(&i) {
+                empty.push(i);
+            }
+        }
+    }
+    // Remove from the end so the indexes stay valid
+    empty.sort_unstable();
+    for i in empty.into_iter().rev() {
+        stmts.remove(i);
+    }
+}
+
+/// Counts the references to each identifier.
+#[derive(Default)]
+struct ReferenceVisitor {
+    /// The number of times each identifier appears.
+    counts: HashMap<Id, usize>
+}
+
+impl Visit for ReferenceVisitor {
+    fn visit_ident(&mut self, id: &Ident) {
+        *self.counts.entry(id.to_id()).or_default() += 1;
+    }
+}
+
+/// Finds unlabeled `break` and `continue` statements that jump out of the
+/// statement being visited.
+#[derive(Default)]
+struct LoopJumpVisitor {
+    /// If the visitor is inside a loop nested in the statement.
+    /// This is only used internally.
+    is_inside_loop: bool,
+
+    /// If the visitor is inside a switch nested in the statement.
+    /// This is only used internally.
+    is_inside_switch: bool,
+
+    /// If a jump was found.
+    found: bool
+}
+
+impl Visit for LoopJumpVisitor {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Break(break_stmt) if break_stmt.label.is_none() => {
+                if !self.is_inside_loop && !self.is_inside_switch {
+                    self.found = true;
+                }
+            },
+            Stmt::Continue(continue_stmt) if continue_stmt.label.is_none() => {
+                if !self.is_inside_loop {
+                    self.found = true;
+                }
+            },
+            Stmt::While(..) | Stmt::DoWhile(..) | Stmt::For(..) | Stmt::ForIn(..) | Stmt::ForOf(..) => {
+                let old_is_inside_loop = self.is_inside_loop;
+                self.is_inside_loop = true;
+                stmt.visit_children_with(self);
+                self.is_inside_loop = old_is_inside_loop;
+            },
+            Stmt::Switch(..) => {
+                let old_is_inside_switch = self.is_inside_switch;
+                self.is_inside_switch = true;
+                stmt.visit_children_with(self);
+                self.is_inside_switch = old_is_inside_switch;
+            },
+            _ => stmt.visit_children_with(self)
+        }
+    }
+
+    // Jumps can't cross function boundaries
+    fn visit_function(&mut self, _: &Function) {}
+
+    fn visit_arrow_expr(&mut self, _: &ArrowExpr) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::deobfuscate::{run_passes, Pass};
+
+    #[test]
+    fn test_split_order() {
+        let code = r#"function f() {
+            var o = '2|0|1'['split']('|'), i = 0x0;
+            while (!![]) {
+                switch (o[i++]) {
+                    case '0': b(); continue;
+                    case '1': return c();
+                    case '2': var x = a(); continue;
+                }
+                break;
+            }
+        }"#;
+
+        assert_eq!(run_passes(code, &[Pass::Resolver, Pass::ControlFlow]).code, "function f() {\n    var x = a();\n    b();\n    return c();\n}\n");
+    }
+
+    #[test]
+    fn test_array_order() {
+        let code = r#"var o = [1, 0], i = 0, keep = 1;
+            while (true) {
+                switch (o[i++]) {
+                    case 0: b(); continue;
+                    case 1: a(); continue;
+                }
+                break;
+            }"#;
+
+        assert_eq!(run_passes(code, &[Pass::Resolver, Pass::ControlFlow]).code, "var keep = 1;\na();\nb();\n");
+    }
+
+    #[test]
+    fn test_unproven_order() {
+        // The counter is changed inside a case
+        let code = r#"var o = '1|0'.split('|'), i = 0;
+            while (true) {
+                switch (o[i++]) {
+                    case '0': b(); i = 0; continue;
+                    case '1': a(); continue;
+                }
+                break;
+            }"#;
+        assert!(run_passes(code, &[Pass::Resolver, Pass::ControlFlow]).code.contains("switch"));
+
+        // A case is missing from the order
+        let code = r#"var o = '1'.split('|'), i = 0;
+            while (true) {
+                switch (o[i++]) {
+                    case '0': b(); continue;
+                    case '1': a(); continue;
+                }
+                break;
+            }"#;
+        assert!(run_passes(code, &[Pass::Resolver, Pass::ControlFlow]).code.contains("switch"));
+
+        // A case breaks out of the loop
+        let code = r#"var o = '1|0'.split('|'), i = 0;
+            while (true) {
+                switch (o[i++]) {
+                    case '0': if (b()) break; continue;
+                    case '1': a(); continue;
+                }
+                break;
+            }"#;
+        assert!(run_passes(code, &[Pass::Resolver, Pass::ControlFlow]).code.contains("switch"));
+    }
+
+    #[test]
+    fn test_escaped_counter() {
+        // A closure outside of the block can change the counter
+        let code = r#"function f() {
+            var g = function() { i = 0; };
+            if (x) {
+                var o = '1|0'.split('|'), i = 0;
+                while (true) {
+                    switch (o[i++]) {
+                        case '0': b(); continue;
+                        case '1': g(); continue;
+                    }
+                    break;
+                }
+            }
+        }"#;
+        assert!(run_passes(code, &[Pass::Resolver, Pass::ControlFlow]).code.contains("switch"));
+    }
+}
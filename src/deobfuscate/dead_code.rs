@@ -0,0 +1,400 @@
+use std::collections::{HashMap, HashSet};
+use swc_core::common::util::take::Take;
+use swc_core::ecma::ast::{AssignExpr, BinaryOp, BindingIdent, ClassDecl, Decl, Expr, FnDecl, Id, Ident, Lit, ModuleItem, Pat, PatOrExpr, Program, Stmt, UnaryOp, UpdateExpr, VarDecl, VarDeclarator, VarDeclKind};
+use swc_core::ecma::atoms::JsWord;
+use swc_core::ecma::visit::{VisitMut, VisitMutWith};
+
+/// Removes code injected by obfuscator.io's `deadCodeInjection` option by
+/// evaluating opaque predicates and keeping the branch that runs.
+///
+/// Example:
+/// ```js
+/// if ('xYzAb' === 'xYzAb') {
+///     real();
+/// } else {
+///     junk();
+/// }
+/// ```
+///
+/// is replaced with:
+///
+/// ```js
+/// real();
+/// ```
+///
+/// Predicates can use `===`, `!==` and variables that are only assigned once,
+/// and ternaries are handled the same way. This should run after
+/// [crate::deobfuscate::strings::Visitor], as the strings compared are often obfuscated.
+#[derive(Default)]
+pub struct Visitor {
+    /// Variables that always hold the same literal value.
+    constants: HashMap<Id, Value>,
+
+    /// The constants that were used to evaluate a predicate.
+    used_constants: HashSet<Id>
+}
+
+/// A literal value in a predicate.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Str(JsWord),
+    Num(f64),
+    Bool(bool)
+}
+
+impl Visitor {
+    /// Evaluates `expr` to a literal value.
+    fn eval(&mut self, expr: &Expr) -> Option<Value> {
+        match expr {
+            Expr::Lit(Lit::Str(s)) => Some(Value::Str(s.value.clone())),
+            Expr::Lit(Lit::Num(n)) => Some(Value::Num(n.value)),
+            Expr::Lit(Lit::Bool(b)) => Some(Value::Bool(b.value)),
+            Expr::Paren(paren) => self.eval(&paren.expr),
+            Expr::Ident(id) => {
+                let value = self.constants.get(&id.to_id())?.clone();
+                self.used_constants.insert(id.to_id());
+                Some(value)
+            },
+            Expr::Unary(unary) if unary.op == UnaryOp::Bang => match self.eval(&unary.arg)? {
+                Value::Bool(b) => Some(Value::Bool(!b)),
+                _ => None
+            },
+            Expr::Bin(bin) => {
+                let equal = match bin.op {
+                    BinaryOp::EqEqEq | BinaryOp::EqEq => true,
+                    BinaryOp::NotEqEq | BinaryOp::NotEq => false,
+                    _ => return None
+                };
+                let left = self.eval(&bin.left)?;
+                let right = self.eval(&bin.right)?;
+                // Loose equality converts values of different types, which isn't needed here
+                if std::mem::discriminant(&left) != std::mem::discriminant(&right) {
+                    return match bin.op {
+                        BinaryOp::EqEqEq => Some(Value::Bool(false)),
+                        BinaryOp::NotEqEq => Some(Value::Bool(true)),
+                        _ => None
+                    };
+                }
+                Some(Value::Bool((left == right) == equal))
+            },
+            _ => None
+        }
+    }
+
+    /// Replaces `stmt` with the branch that runs if it's an `if` statement with a known
+    /// predicate, or an empty statement if no branch runs. Returns if it was replaced.
+    fn remove_dead_branch(&mut self, stmt: &mut Stmt) -> bool {
+        let Stmt::If(if_stmt) = stmt else {
+            return false;
+        };
+        let Some(predicate) = self.eval_predicate(&if_stmt.test) else {
+            return false;
+        };
+
+        let branch = if predicate {
+            Some(if_stmt.cons.take())
+        } else {
+            if_stmt.alt.take()
+        };
+        match branch {
+            Some(branch) => *stmt = *branch,
+            None => {
+                stmt.take();
+            }
+        }
+        true
+    }
+
+    /// Visits `stmt` and adds it to `stmts`, without empty statements. If a block replaced
+    /// a dead branch, its statements are added instead, unless it declares `let`, `const`,
+    /// classes or functions, as their scope would change.
+    fn add_stmt(&mut self, mut stmt: Stmt, stmts: &mut Vec<Stmt>) {
+        stmt.visit_mut_children_with(self);
+        let replaced = self.remove_dead_branch(&mut stmt);
+
+        match stmt {
+            Stmt::Empty(..) => {},
+            Stmt::Block(block) if replaced && !block.stmts.iter().any(is_scoped_decl) => {
+                stmts.extend(block.stmts);
+            },
+            stmt => stmts.push(stmt)
+        }
+    }
+
+    /// Evaluates a predicate.
+    fn eval_predicate(&mut self, expr: &Expr) -> Option<bool> {
+        match self.eval(expr)? {
+            Value::Bool(b) => Some(b),
+            _ => None
+        }
+    }
+}
+
+impl VisitMut for Visitor {
+    fn visit_mut_program(&mut self, program: &mut Program) {
+        // Find variables that are only assigned once, to a literal or a predicate
+        let mut constants_visitor = ConstantsVisitor::default();
+        program.visit_mut_children_with(&mut constants_visitor);
+        let declarators = constants_visitor.declarators;
+        for (id, init) in &declarators {
+            if constants_visitor.writes.get(id) != Some(&1) {
+                continue;
+            }
+            if let Some(value) = self.eval(init) {
+                self.constants.insert(id.clone(), value);
+            }
+        }
+
+        // Remove dead branches
+        program.visit_mut_children_with(self);
+
+        // Remove the constants that were only used by predicates. A constant can
+        // be used by another constant, so this is repeated until every constant
+        // left is unused by the code that is kept.
+        let mut unused = self.used_constants.clone();
+        loop {
+            let mut references_visitor = ReferencesVisitor {
+                skipped: &unused,
+                references: HashSet::new()
+            };
+            program.visit_mut_children_with(&mut references_visitor);
+            let references = references_visitor.references;
+
+            let count = unused.len();
+            unused.retain(|id| !references.contains(id));
+            if unused.len() == count {
+                break;
+            }
+        }
+        if !unused.is_empty() {
+            let mut remove_visitor = RemoveDeclaratorsVisitor { ids: unused };
+            program.visit_mut_children_with(&mut remove_visitor);
+        }
+    }
+
+    fn visit_mut_stmt(&mut self, stmt: &mut Stmt) {
+        stmt.visit_mut_children_with(self);
+        self.remove_dead_branch(stmt);
+    }
+
+    fn visit_mut_stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        let mut flattened = Vec::with_capacity(stmts.len());
+        for stmt in stmts.take() {
+            self.add_stmt(stmt, &mut flattened);
+        }
+        *stmts = flattened;
+    }
+
+    fn visit_mut_module_items(&mut self, items: &mut Vec<ModuleItem>) {
+        let mut flattened = Vec::with_capacity(items.len());
+        for mut item in items.take() {
+            match item {
+                ModuleItem::Stmt(stmt) => {
+                    let mut stmts = Vec::new();
+                    self.add_stmt(stmt, &mut stmts);
+                    flattened.extend(stmts.into_iter().map(ModuleItem::Stmt));
+                },
+                _ => {
+                    item.visit_mut_children_with(self);
+                    flattened.push(item);
+                }
+            }
+        }
+        *items = flattened;
+    }
+
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        expr.visit_mut_children_with(self);
+
+        if let Expr::Cond(cond) = expr {
+            if let Some(predicate) = self.eval_predicate(&cond.test) {
+                *expr = if predicate {
+                    *cond.cons.take()
+                } else {
+                    *cond.alt.take()
+                };
+            }
+        }
+    }
+}
+
+/// If `stmt` is a declaration scoped to its block.
+fn is_scoped_decl(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Decl(Decl::Var(var)) => var.kind != VarDeclKind::Var,
+        Stmt::Decl(..) => true,
+        _ => false
+    }
+}
+
+/// Finds variable declarators and counts how many times each variable is written to.
+#[derive(Default)]
+struct ConstantsVisitor {
+    /// The init of each variable declarator.
+    declarators: Vec<(Id, Expr)>,
+
+    /// The number of times each variable is declared or assigned to.
+    writes: HashMap<Id, usize>
+}
+
+impl VisitMut for ConstantsVisitor {
+    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        declarator.visit_mut_children_with(self);
+
+        if let (Pat::Ident(id), Some(init)) = (&declarator.name, &declarator.init) {
+            self.declarators.push((id.to_id(), (**init).clone()));
+        }
+    }
+
+    // Declarations, parameters and assignments
+    fn visit_mut_binding_ident(&mut self, id: &mut BindingIdent) {
+        *self.writes.entry(id.to_id()).or_default() += 1;
+    }
+
+    fn visit_mut_assign_expr(&mut self, assign: &mut AssignExpr) {
+        assign.visit_mut_children_with(self);
+
+        if let PatOrExpr::Expr(expr) = &assign.left {
+            if let Expr::Ident(id) = &**expr {
+                *self.writes.entry(id.to_id()).or_default() += 1;
+            }
+        }
+    }
+
+    fn visit_mut_update_expr(&mut self, update: &mut UpdateExpr) {
+        update.visit_mut_children_with(self);
+
+        if let Expr::Ident(id) = &*update.arg {
+            *self.writes.entry(id.to_id()).or_default() += 1;
+        }
+    }
+
+    fn visit_mut_fn_decl(&mut self, fn_decl: &mut FnDecl) {
+        fn_decl.visit_mut_children_with(self);
+        *self.writes.entry(fn_decl.ident.to_id()).or_default() += 1;
+    }
+
+    fn visit_mut_class_decl(&mut self, class_decl: &mut ClassDecl) {
+        class_decl.visit_mut_children_with(self);
+        *self.writes.entry(class_decl.ident.to_id()).or_default() += 1;
+    }
+}
+
+/// Finds the identifiers that are referenced, not counting declarations.
+struct ReferencesVisitor<'a> {
+    /// The variables whose declarators are skipped, including their init.
+    skipped: &'a HashSet<Id>,
+
+    /// The referenced identifiers.
+    references: HashSet<Id>
+}
+
+impl<'a> VisitMut for ReferencesVisitor<'a> {
+    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        if let Pat::Ident(id) = &declarator.name {
+            if self.skipped.contains(&id.to_id()) {
+                return;
+            }
+        }
+        // Skip the name
+        declarator.init.visit_mut_with(self);
+    }
+
+    fn visit_mut_ident(&mut self, id: &mut Ident) {
+        self.references.insert(id.to_id());
+    }
+}
+
+/// Removes the declarators of unused constants.
+struct RemoveDeclaratorsVisitor {
+    /// The constants to remove.
+    ids: HashSet<Id>
+}
+
+impl VisitMut for RemoveDeclaratorsVisitor {
+    fn visit_mut_var_decl(&mut self, var: &mut VarDecl) {
+        var.decls.retain(|declarator| match &declarator.name {
+            Pat::Ident(id) => !self.ids.contains(&id.to_id()),
+            _ => true
+        });
+    }
+
+    fn visit_mut_stmt(&mut self, stmt: &mut Stmt) {
+        stmt.visit_mut_children_with(self);
+
+        if let Stmt::Decl(Decl::Var(var)) = stmt {
+            if var.decls.is_empty() {
+                stmt.take();
+            }
+        }
+    }
+
+    // Remove empty statements
+    fn visit_mut_stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        stmts.visit_mut_children_with(self);
+
+        stmts.retain(|s| !matches!(s, Stmt::Empty(..)));
+    }
+
+    // Remove empty ModuleItem's
+    fn visit_mut_module_items(&mut self, stmts: &mut Vec<ModuleItem>) {
+        stmts.visit_mut_children_with(self);
+        stmts.retain(|stmt| !matches!(stmt, ModuleItem::Stmt(Stmt::Empty(..))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use swc_core::ecma::ast::{Program, Stmt};
+    use crate::deobfuscate::{run_passes, Pass};
+
+    #[test]
+    fn test_if() {
+        let code = r#"if ('xYzAb' === 'xYzAb') { real(); } else { junk(); }
+            if ('aBc' !== 'aBc') { junk(); } else { other(); }
+            if ('a' === 'b') junk();"#;
+
+        assert_eq!(run_passes(code, &[Pass::Resolver, Pass::DeadCode]).code, "real();\nother();\n");
+    }
+
+    #[test]
+    fn test_ternary() {
+        let code = r#"var f = 'Qw' !== 'Er' ? function() { real(); } : function() { junk(); };"#;
+
+        assert_eq!(run_passes(code, &[Pass::Resolver, Pass::DeadCode]).code, "var f = function() {\n    real();\n};\n");
+    }
+
+    #[test]
+    fn test_variable_predicate() {
+        let code = r#"function f() {
+            var p = 'kLm', q = p === 'kLm';
+            if (q) { real(); } else { junk(); }
+        }"#;
+
+        assert_eq!(run_passes(code, &[Pass::Resolver, Pass::DeadCode]).code, "function f() {\n    real();\n}\n");
+    }
+
+    #[test]
+    fn test_unknown_predicate() {
+        // The variable is assigned again, so its value isn't known
+        let code = r#"var p = 'a'; p = b(); if (p === 'a') { c(); } else { d(); }"#;
+
+        assert!(run_passes(code, &[Pass::Resolver, Pass::DeadCode]).code.contains("if (p === 'a')"));
+    }
+
+    #[test]
+    fn test_scoped_branch() {
+        // The block declares a `let`, so it's kept along with its position
+        let code = r#"if ('a' === 'a') { let x = 1; real(x); }"#;
+        let output = run_passes(code, &[Pass::Resolver, Pass::DeadCode]);
+        assert_eq!(output.code, "{\n    let x = 1;\n    real(x);\n}");
+
+        let Program::Script(script) = output.program else {
+            panic!("not a script");
+        };
+        let Stmt::Block(block) = &script.body[0] else {
+            panic!("not a block");
+        };
+        assert!(!block.span.is_dummy());
+    }
+}
@@ -0,0 +1,488 @@
+use std::collections::HashSet;
+use swc_core::ecma::ast::{ArrowExpr, BlockStmtOrExpr, CatchClause, ClassDecl, Constructor, Expr, ExprStmt, FnDecl, Function, GetterProp, Ident, LabeledStmt, Lit, MetaPropExpr, MetaPropKind, Param, Pat, ReturnStmt, SetterProp, Stmt, ThisExpr, VarDeclarator};
+use swc_core::ecma::atoms::JsWord;
+use swc_core::ecma::visit::{Visit, VisitMut, VisitMutWith, VisitWith};
+
+/// Inlines IIFEs (Immediately Invoked Function Expressions) that take no arguments.
+///
+/// Example:
+/// ```js
+/// function challenge(a) {
+///     return function() {
+///         var e = a * 2;
+///         return [e];
+///     }();
+/// }
+/// ```
+///
+/// is replaced with:
+///
+/// ```js
+/// function challenge(a) {
+///     var e = a * 2;
+///     return [e];
+/// }
+/// ```
+///
+/// IIFEs can be inlined when they're a statement or returned. They're left alone if they
+/// use `this`, `arguments` or `new.target`, return anywhere but at the end, or declare
+/// names that could clash with the code around them. IIFEs that declare names are only
+/// inlined into function bodies, where each call still gets its own variables, and not
+/// into the script, where they would become globals.
+pub struct Visitor;
+
+impl Visitor {
+    /// Inlines the IIFEs in `stmts`. `scope_names` is [Some] with the function's
+    /// parameter names if `stmts` is a function body, in which case IIFEs that
+    /// declare names can be inlined.
+    fn inline_iifes(&mut self, stmts: &mut Vec<Stmt>, scope_names: Option<HashSet<JsWord>>) {
+        let mut i = 0;
+        while i < stmts.len() {
+            match self.inline_stmt(stmts, i, scope_names.as_ref()) {
+                Some(body) => {
+                    let len = body.len();
+                    stmts.splice(i..=i, body);
+                    i += len;
+                },
+                None => i += 1
+            }
+        }
+    }
+
+    /// Returns the statements to replace `stmts[index]` with, if it can be inlined.
+    fn inline_stmt(&self, stmts: &[Stmt], index: usize, scope_names: Option<&HashSet<JsWord>>) -> Option<Vec<Stmt>> {
+        // The span of the return statement the IIFE is returned from
+        let (mut body, return_span) = match &stmts[index] {
+            Stmt::Expr(ExprStmt { expr, .. }) => (iife_body(expr)?, None),
+            Stmt::Return(ReturnStmt { span, arg: Some(arg) }) => (iife_body(arg)?, Some(*span)),
+            _ => return None
+        };
+
+        // Returns can only be the last statement, where they're replaced
+        let mut returns_visitor = ReturnsVisitor::default();
+        body.visit_children_with(&mut returns_visitor);
+        let ends_with_return = matches!(body.last(), Some(Stmt::Return(..)));
+        if returns_visitor.returns != ends_with_return as usize || returns_visitor.has_labels {
+            return None;
+        }
+
+        // The IIFE's directives, like "use strict", would no longer apply to it
+        if body.first().is_some_and(is_string_stmt) {
+            return None;
+        }
+
+        // Declared names are moved into the surrounding function,
+        // so they can't be used anywhere else in it
+        let mut declarations_visitor = DeclarationsVisitor::default();
+        body.visit_children_with(&mut declarations_visitor);
+        if !declarations_visitor.names.is_empty() {
+            let mut names_visitor = NamesVisitor {
+                names: scope_names?.clone()
+            };
+            for (i, stmt) in stmts.iter().enumerate() {
+                if i != index {
+                    stmt.visit_with(&mut names_visitor);
+                }
+            }
+            if !declarations_visitor.names.is_disjoint(&names_visitor.names) {
+                return None;
+            }
+        }
+
+        if let Some(span) = return_span {
+            // Falling off the end returned undefined
+            if !ends_with_return {
+                body.push(Stmt::Return(ReturnStmt {
+                    span,
+                    arg: None
+                }));
+            }
+        } else if ends_with_return {
+            // The value was unused, but it may have side effects
+            if let Some(Stmt::Return(ReturnStmt { span, arg: Some(arg) })) = body.pop() {
+                body.push(Stmt::Expr(ExprStmt { span, expr: arg }));
+            }
+        }
+
+        // A string statement that ends up at the start of the function or script,
+        // after its directives, would become a directive itself
+        let next = body.iter().chain(&stmts[index + 1..]).next();
+        if stmts[..index].iter().all(is_string_stmt) && next.is_some_and(is_string_stmt) {
+            return None;
+        }
+
+        Some(body)
+    }
+}
+
+/// If `stmt` is a statement made of a string literal, like a directive.
+fn is_string_stmt(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Expr(ExprStmt { expr, .. }) => matches!(&**expr, Expr::Lit(Lit::Str(..))),
+        _ => false
+    }
+}
+
+/// Returns a copy of the body of `expr` if it's an IIFE that takes no arguments
+/// and doesn't use anything bound by the function itself.
+fn iife_body(expr: &Expr) -> Option<Vec<Stmt>> {
+    let Expr::Call(call) = expr else {
+        return None;
+    };
+    if !call.args.is_empty() {
+        return None;
+    }
+    let mut callee = call.callee.as_expr()?;
+    while let Expr::Paren(paren) = &**callee {
+        callee = &paren.expr;
+    }
+
+    match &**callee {
+        Expr::Fn(fn_expr) => {
+            let function = &fn_expr.function;
+            if fn_expr.ident.is_some() || !function.params.is_empty() || function.is_async || function.is_generator {
+                return None;
+            }
+            let body = &function.body.as_ref()?.stmts;
+
+            let mut this_visitor = ThisVisitor::default();
+            body.visit_children_with(&mut this_visitor);
+            if this_visitor.found {
+                return None;
+            }
+            Some(body.clone())
+        },
+        // Arrow functions use the surrounding `this` and `arguments`
+        Expr::Arrow(arrow) => {
+            if !arrow.params.is_empty() || arrow.is_async || arrow.is_generator {
+                return None;
+            }
+            match &*arrow.body {
+                BlockStmtOrExpr::BlockStmt(body) => Some(body.stmts.clone()),
+                BlockStmtOrExpr::Expr(expr) => Some(vec![Stmt::Return(ReturnStmt {
+                    span: arrow.span,
+                    arg: Some(expr.clone())
+                })])
+            }
+        },
+        _ => None
+    }
+}
+
+/// Adds the names bound by `pat` to `names`.
+fn add_pat_names(pat: &Pat, names: &mut HashSet<JsWord>) {
+    let mut names_visitor = NamesVisitor {
+        names: HashSet::new()
+    };
+    pat.visit_with(&mut names_visitor);
+    names.extend(names_visitor.names);
+}
+
+impl VisitMut for Visitor {
+    fn visit_mut_function(&mut self, function: &mut Function) {
+        function.params.visit_mut_with(self);
+        function.decorators.visit_mut_with(self);
+
+        if let Some(body) = &mut function.body {
+            // Visit the statements themselves, so the body is only inlined into once
+            body.stmts.visit_mut_children_with(self);
+
+            let mut params = HashSet::new();
+            for Param { pat, .. } in &function.params {
+                add_pat_names(pat, &mut params);
+            }
+            self.inline_iifes(&mut body.stmts, Some(params));
+        }
+    }
+
+    fn visit_mut_arrow_expr(&mut self, arrow: &mut ArrowExpr) {
+        arrow.params.visit_mut_with(self);
+
+        match &mut *arrow.body {
+            BlockStmtOrExpr::BlockStmt(body) => {
+                body.stmts.visit_mut_children_with(self);
+
+                let mut params = HashSet::new();
+                for pat in &arrow.params {
+                    add_pat_names(pat, &mut params);
+                }
+                self.inline_iifes(&mut body.stmts, Some(params));
+            },
+            BlockStmtOrExpr::Expr(expr) => expr.visit_mut_with(self)
+        }
+    }
+
+    // Other statement lists can only have IIFEs that don't declare anything inlined.
+    // This includes the script, where declared names would become globals
+    fn visit_mut_stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        stmts.visit_mut_children_with(self);
+
+        self.inline_iifes(stmts, None);
+    }
+}
+
+/// Finds uses of `this`, `arguments` and `new.target` that belong to a function.
+#[derive(Default)]
+struct ThisVisitor {
+    /// If any were found.
+    found: bool
+}
+
+impl Visit for ThisVisitor {
+    fn visit_this_expr(&mut self, _: &ThisExpr) {
+        self.found = true;
+    }
+
+    fn visit_meta_prop_expr(&mut self, meta_prop: &MetaPropExpr) {
+        if meta_prop.kind == MetaPropKind::NewTarget {
+            self.found = true;
+        }
+    }
+
+    fn visit_ident(&mut self, ident: &Ident) {
+        if &*ident.sym == "arguments" {
+            self.found = true;
+        }
+    }
+
+    // Functions have their own `this`, but arrow functions don't
+    fn visit_function(&mut self, _: &Function) {}
+}
+
+/// Counts the `return` statements that belong to a function, and finds labels.
+#[derive(Default)]
+struct ReturnsVisitor {
+    /// The number of `return` statements.
+    returns: usize,
+
+    /// If there are any labeled statements, which could clash with labels around the IIFE.
+    has_labels: bool
+}
+
+impl Visit for ReturnsVisitor {
+    fn visit_return_stmt(&mut self, return_stmt: &ReturnStmt) {
+        self.returns += 1;
+        return_stmt.visit_children_with(self);
+    }
+
+    fn visit_labeled_stmt(&mut self, labeled: &LabeledStmt) {
+        self.has_labels = true;
+        labeled.visit_children_with(self);
+    }
+
+    // Nested functions have their own returns and labels
+    fn visit_function(&mut self, _: &Function) {}
+
+    fn visit_arrow_expr(&mut self, _: &ArrowExpr) {}
+
+    fn visit_constructor(&mut self, _: &Constructor) {}
+
+    fn visit_getter_prop(&mut self, _: &GetterProp) {}
+
+    fn visit_setter_prop(&mut self, _: &SetterProp) {}
+}
+
+/// Finds the names declared by a function's body, not including nested functions.
+#[derive(Default)]
+struct DeclarationsVisitor {
+    /// The declared names.
+    names: HashSet<JsWord>
+}
+
+impl Visit for DeclarationsVisitor {
+    fn visit_var_declarator(&mut self, declarator: &VarDeclarator) {
+        add_pat_names(&declarator.name, &mut self.names);
+        declarator.visit_children_with(self);
+    }
+
+    fn visit_fn_decl(&mut self, fn_decl: &FnDecl) {
+        self.names.insert(fn_decl.ident.sym.clone());
+    }
+
+    fn visit_class_decl(&mut self, class_decl: &ClassDecl) {
+        self.names.insert(class_decl.ident.sym.clone());
+        class_decl.visit_children_with(self);
+    }
+
+    fn visit_catch_clause(&mut self, catch: &CatchClause) {
+        if let Some(param) = &catch.param {
+            add_pat_names(param, &mut self.names);
+        }
+        catch.visit_children_with(self);
+    }
+
+    fn visit_function(&mut self, _: &Function) {}
+
+    fn visit_arrow_expr(&mut self, _: &ArrowExpr) {}
+}
+
+/// Finds every identifier name.
+struct NamesVisitor {
+    /// The names found.
+    names: HashSet<JsWord>
+}
+
+impl Visit for NamesVisitor {
+    fn visit_ident(&mut self, ident: &Ident) {
+        self.names.insert(ident.sym.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use swc_core::common::BytePos;
+    use swc_core::ecma::ast::{Decl, Program, ReturnStmt, Stmt};
+    use crate::deobfuscate::{Deobfuscator, Pass};
+
+    fn deobfuscate(code: &str) -> String {
+        Deobfuscator::new()
+            .passes(&[Pass::Iife])
+            .deobfuscate(code.to_string())
+            .expect("deobfuscate failed")
+            .code
+    }
+
+    #[test]
+    fn test_inline() {
+        let code = r#"(function(a) {
+    (function() {})();
+    (() => {
+        f(a);
+    })();
+    if (a) {
+        (function() {
+            g();
+            return h();
+        })();
+    }
+    return function() {
+        var e = a * 2;
+        return [e];
+    }();
+});
+function f() {
+    return (() => 1)();
+}
+"#;
+        assert_eq!(deobfuscate(code), r#"(function(a) {
+    f(a);
+    if (a) {
+        g();
+        h();
+    }
+    var e = a * 2;
+    return [
+        e
+    ];
+});
+function f() {
+    return 1;
+}
+"#);
+    }
+
+    #[test]
+    fn test_unsafe_iifes() {
+        let code = r#"function f(e) {
+    (function() {
+        var e = 1;
+    })();
+    (function() {
+        return this;
+    })();
+    (function() {
+        g(arguments);
+    })();
+    (function(x) {})(1);
+    (function() {
+        if (e) return;
+        g();
+    })();
+    for(;;){
+        (function() {
+            var t = e;
+        })();
+    }
+    return function() {
+        "use strict";
+        return e;
+    }();
+}
+"#;
+        assert_eq!(deobfuscate(code), code);
+    }
+
+    #[test]
+    fn test_directives() {
+        // Each of these would leave a string statement at the start of the function
+        let code = r#"function f() {
+    (function() {
+        return "use strict";
+    })();
+    g();
+}
+function h() {
+    "use strict";
+    (function() {})();
+    "use asm";
+}
+"#;
+        assert_eq!(deobfuscate(code), code);
+    }
+
+    #[test]
+    fn test_spans() {
+        // The returns created for the inlined code keep the span of the code they replace
+        let code = r#"function f() {
+    return (() => 1)();
+}
+function g() {
+    return function() {
+        h();
+    }();
+}
+"#;
+        let program = Deobfuscator::new()
+            .passes(&[Pass::Iife])
+            .deobfuscate(code.to_string())
+            .expect("deobfuscate failed")
+            .program;
+        let Program::Script(script) = program else {
+            panic!("not a script");
+        };
+        let returns: Vec<BytePos> = script.body
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Decl(Decl::Fn(fn_decl)) => fn_decl.function.body.as_ref()?.stmts.last(),
+                _ => None
+            })
+            .filter_map(|stmt| match stmt {
+                Stmt::Return(ReturnStmt { span, .. }) => Some(span.lo),
+                _ => None
+            })
+            .collect();
+        let pos = |text: &str| script.span.lo + BytePos(code.find(text).expect("code not found") as u32);
+        assert_eq!(returns, vec![pos("() => 1"), pos("return function")]);
+    }
+
+    #[test]
+    fn test_script_iifes() {
+        // Names declared at script level would be globals
+        let code = r#"(function() {
+    var e = 1;
+    function g() {}
+    g(e);
+})();
+(function() {
+    g();
+})();
+"#;
+        assert_eq!(deobfuscate(code), r#"(function() {
+    var e = 1;
+    function g() {}
+    g(e);
+})();
+g();
+"#);
+    }
+}
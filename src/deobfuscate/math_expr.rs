@@ -18,7 +18,10 @@ pub struct Visitor {
     is_inside_correct_expr: bool,
 
     /// The computed answer from the math expression.
-    pub answer: Option<f64>
+    pub answer: Option<f64>,
+
+    /// The math expression the answer was computed from, before it was computed.
+    pub expression: Option<Expr>
 }
 
 impl Visitor {
@@ -29,7 +32,8 @@ impl Visitor {
             input_param: None,
             is_inside_array_lit: false,
             is_inside_correct_expr: false,
-            answer: None
+            answer: None,
+            expression: None
         }
     }
 }
@@ -99,7 +103,7 @@ fn compute_call(fn_name: &str, args: &[f64]) -> Option<f64> {
         "round" => Some(get_arg!(0).round()),
         "sign" => Some({
             let v = get_arg!(0);
-            if v == f64::NAN {
+            if v.is_nan() {
                 f64::NAN
             } else if v > 0.0 {
                 1.0
@@ -121,8 +125,8 @@ fn compute_call(fn_name: &str, args: &[f64]) -> Option<f64> {
 
 impl VisitMut for Visitor {
     fn visit_mut_fn_expr(&mut self, fn_expr: &mut FnExpr) {
-        if !self.input_param.is_some() {
-            if let Some(param) = fn_expr.function.params.get(0) {
+        if self.input_param.is_none() {
+            if let Some(param) = fn_expr.function.params.first() {
                 if let Pat::Ident(input_param) = &param.pat {
                     self.input_param = Some(input_param.to_id());
                 }
@@ -133,7 +137,7 @@ impl VisitMut for Visitor {
     }
 
     fn visit_mut_array_lit(&mut self, array_lit: &mut ArrayLit) {
-        if let Some(Some(_)) = array_lit.elems.get(0) {
+        if let Some(Some(_)) = array_lit.elems.first() {
             let old_is_inside_array_lit = self.is_inside_array_lit;
             self.is_inside_array_lit = true;
             array_lit.visit_mut_children_with(self);
@@ -147,6 +151,7 @@ impl VisitMut for Visitor {
         if self.is_inside_array_lit {
             let old_is_inside_correct_expr = self.is_inside_correct_expr;
             self.is_inside_correct_expr = true;
+            let original = (self.answer.is_none() && !old_is_inside_correct_expr).then(|| expr.clone());
             expr.visit_mut_children_with(self);
 
             if self.answer.is_none() && self.is_inside_correct_expr && !old_is_inside_correct_expr {
@@ -163,6 +168,7 @@ impl VisitMut for Visitor {
                 if let Stmt::Expr(expr_stmt) = &stmt {
                     if let Expr::Lit(Lit::Num(number)) = &*expr_stmt.expr {
                         self.answer = Some(number.value);
+                        self.expression = original;
                         *expr = Expr::Lit(Lit::Num(Number::from(number.value)));
                     }
                 }
@@ -1,4 +1,23 @@
 pub mod strings;
 pub mod proxy_vars;
+pub mod proxy_objects;
 pub mod math_expr;
 pub mod computed_member_expr;
+pub mod string_concat;
+pub mod control_flow;
+pub mod anti_analysis;
+pub mod rename;
+pub mod dead_code;
+pub mod iife;
+mod pipeline;
+
+pub use pipeline::{Deobfuscator, DeobfuscateError, DeobfuscationReport, FixedPoint, Output, Pass, Round};
+
+/// Runs `passes` over `code`, panicking if it fails.
+#[cfg(test)]
+pub(crate) fn run_passes(code: &str, passes: &[Pass]) -> Output {
+    Deobfuscator::new()
+        .passes(passes)
+        .deobfuscate(code.to_string())
+        .expect("deobfuscate failed")
+}
@@ -0,0 +1,680 @@
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::sync::Arc;
+use swc::config::{IsModule, SourceMapsConfig};
+use swc_core::common::{EqIgnoreSpan, FileName, GLOBALS, Globals, Mark, SourceFile, SourceMap, Spanned};
+use swc_core::common::errors::{EmitterWriter, Handler};
+use swc_core::ecma::ast::{EsVersion, Expr, ExprStmt, ParenExpr, Program, Script, Stmt};
+use swc_core::ecma::codegen::{Config, Emitter, Node};
+use swc_core::ecma::codegen::text_writer::JsWriter;
+use swc_core::ecma::visit::VisitMutWith;
+use swc_ecma_parser::{Capturing, EsConfig, Parser, StringInput, Syntax};
+use swc_ecma_parser::lexer::Lexer;
+use swc_ecma_transforms::fixer;
+use swc_ecma_transforms::optimization::simplify::expr_simplifier;
+use swc_ecma_transforms::resolver;
+use crate::shared_cursor::SharedCursor;
+use super::{anti_analysis, computed_member_expr, control_flow, dead_code, iife, math_expr, proxy_objects, proxy_vars, rename, string_concat, strings};
+
+/// A deobfuscation pass.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Pass {
+    /// Squashes constant expressions like `4 + 5 * 2` into constant values.
+    ExprSimplifier,
+
+    /// Resolves identifiers to scope-aware values.
+    Resolver,
+
+    /// Removes proxy variables, see [proxy_vars::Visitor].
+    ProxyVars,
+
+    /// Removes string obfuscation, see [strings::Visitor].
+    Strings,
+
+    /// Joins concatenated string literals, see [string_concat::Visitor].
+    StringConcat,
+
+    /// Inlines the members of storage objects, see [proxy_objects::Visitor].
+    ProxyObjects,
+
+    /// Removes dead branches of opaque predicates, see [dead_code::Visitor].
+    DeadCode,
+
+    /// Rebuilds flattened control flow, see [control_flow::Visitor].
+    ControlFlow,
+
+    /// Removes self-defending, debug protection and console disabling code,
+    /// see [anti_analysis::Visitor].
+    AntiAnalysis,
+
+    /// Converts expressions like `Math["floor"]` to `Math.floor`,
+    /// see [computed_member_expr::Visitor].
+    ComputedMemberExpr,
+
+    /// Inlines IIFEs that take no arguments, see [iife::Visitor].
+    Iife,
+
+    /// Renames mangled identifiers based on their role, see [rename::Visitor].
+    /// This pass isn't run by default. [Pass::Resolver] is run before it
+    /// if it isn't enabled earlier.
+    Rename,
+
+    /// Computes the math expression to a constant value, see [math_expr::Visitor].
+    /// This pass requires an input, set with [Deobfuscator::input].
+    MathExpr
+}
+
+impl Pass {
+    /// The passes run by [Deobfuscator::new], in order.
+    pub const DEFAULT: [Pass; 11] = [
+        Pass::ExprSimplifier,
+        Pass::Resolver,
+        Pass::ProxyVars,
+        Pass::Strings,
+        Pass::StringConcat,
+        Pass::ProxyObjects,
+        Pass::DeadCode,
+        Pass::ControlFlow,
+        Pass::AntiAnalysis,
+        Pass::ComputedMemberExpr,
+        Pass::Iife
+    ];
+}
+
+/// A deobfuscation error.
+#[derive(Debug)]
+pub enum DeobfuscateError {
+    /// SWC failed to parse the JavaScript code.
+    ParseError(anyhow::Error),
+
+    /// Failed to parse transform error(s).
+    TransformErrorParseError(std::string::FromUtf8Error),
+
+    /// One or more errors were emitted from a transform.
+    TransformErrors(Vec<String>),
+
+    /// SWC failed to print the deobfuscated code.
+    PrintError(anyhow::Error),
+
+    /// [Pass::MathExpr] is enabled but no input was set.
+    MissingInput
+}
+
+impl Display for DeobfuscateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ParseError(e) => write!(f, "ParseError: {}", e),
+            Self::TransformErrorParseError(e) => write!(f, "TransformErrorParseError: {}", e),
+            Self::TransformErrors(errs) => write!(f, "TransformErrors: {}", errs.join(", ")),
+            Self::PrintError(e) => write!(f, "PrintError: {}", e),
+            Self::MissingInput => write!(f, "MissingInput: the math_expr pass requires an input")
+        }
+    }
+}
+
+impl std::error::Error for DeobfuscateError {}
+
+impl From<anyhow::Error> for DeobfuscateError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::ParseError(err)
+    }
+}
+
+impl From<std::string::FromUtf8Error> for DeobfuscateError {
+    fn from(err: std::string::FromUtf8Error) -> Self {
+        Self::TransformErrorParseError(err)
+    }
+}
+
+impl From<Vec<String>> for DeobfuscateError {
+    fn from(errors: Vec<String>) -> Self {
+        Self::TransformErrors(errors)
+    }
+}
+
+/// The result of [Deobfuscator::deobfuscate].
+pub struct Output {
+    /// The deobfuscated code.
+    pub code: String,
+
+    /// The deobfuscated AST.
+    pub program: Program,
+
+    /// The answer computed by [Pass::MathExpr], if the pass was run
+    /// and the expression could be computed.
+    pub answer: Option<f64>,
+
+    /// What happened in each round, if [Deobfuscator::fixed_point] was set.
+    pub fixed_point: Option<FixedPoint>,
+
+    /// The calls [Pass::Strings] couldn't resolve to a string.
+    pub unresolved_calls: Vec<strings::UnresolvedCall>,
+
+    /// The protections [Pass::AntiAnalysis] removed.
+    pub protections: Vec<anti_analysis::Protection>,
+
+    /// The identifiers [Pass::Rename] renamed, in the order they're declared.
+    pub renamed: Vec<rename::Renamed>,
+
+    /// A v3 source map from `code` back to the script, if
+    /// [Deobfuscator::emit_source_map] was set.
+    pub source_map: Option<String>,
+
+    /// What each pass found, which can be serialised to JSON.
+    pub report: DeobfuscationReport
+}
+
+/// What the passes found and changed, in a form that can be serialised.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct DeobfuscationReport {
+    /// The string arrays [Pass::Strings] decoded.
+    pub string_arrays: Vec<strings::StringArray>,
+
+    /// The proxy variables [Pass::ProxyVars] removed.
+    pub proxies: Vec<proxy_vars::RemovedProxy>,
+
+    /// The members and keys [Pass::ComputedMemberExpr] normalised.
+    pub members: Vec<computed_member_expr::NormalisedMember>,
+
+    /// The math expression [Pass::MathExpr] computed, as it was before computing it.
+    pub math_expression: Option<String>,
+
+    /// The answer computed by [Pass::MathExpr].
+    pub answer: Option<f64>
+}
+
+/// What the passes found while running.
+#[derive(Default)]
+struct PassResults {
+    /// The answer computed by [Pass::MathExpr].
+    answer: Option<f64>,
+
+    /// The calls [Pass::Strings] couldn't resolve to a string.
+    unresolved_calls: Vec<strings::UnresolvedCall>,
+
+    /// The protections [Pass::AntiAnalysis] removed.
+    protections: Vec<anti_analysis::Protection>,
+
+    /// The identifiers [Pass::Rename] renamed.
+    renamed: Vec<rename::Renamed>,
+
+    /// What the passes found, for [Output::report].
+    report: DeobfuscationReport
+}
+
+impl PassResults {
+    /// Adds what `other` found to `self`.
+    fn append(&mut self, mut other: PassResults) {
+        if other.answer.is_some() {
+            self.answer = other.answer;
+        }
+        self.unresolved_calls.append(&mut other.unresolved_calls);
+        for protection in other.protections {
+            if !self.protections.contains(&protection) {
+                self.protections.push(protection);
+            }
+        }
+        self.renamed.append(&mut other.renamed);
+
+        self.report.string_arrays.append(&mut other.report.string_arrays);
+        self.report.proxies.append(&mut other.report.proxies);
+        self.report.members.append(&mut other.report.members);
+        if other.report.answer.is_some() {
+            self.report.math_expression = other.report.math_expression;
+            self.report.answer = other.report.answer;
+        }
+    }
+}
+
+/// The rounds run by [Deobfuscator::fixed_point].
+#[derive(Debug, Clone, Default)]
+pub struct FixedPoint {
+    /// Each round that ran, in order.
+    pub rounds: Vec<Round>,
+
+    /// If the last round didn't change the AST. This is `false` if the
+    /// round limit was reached first.
+    pub converged: bool
+}
+
+/// A single run of every enabled pass.
+#[derive(Debug, Clone, Default)]
+pub struct Round {
+    /// The passes that changed the AST in this round, in the order they ran.
+    pub changed: Vec<Pass>
+}
+
+/// Runs deobfuscation passes over a script.
+///
+/// Example:
+/// ```no_run
+/// use vercel_anti_bot::deobfuscate::{Deobfuscator, Pass};
+///
+/// let output = Deobfuscator::new()
+///     .disable(Pass::ComputedMemberExpr)
+///     .input(0.5)
+///     .enable(Pass::MathExpr)
+///     .deobfuscate(String::from("(function(a){ /* ... */ })"))
+///     .expect("deobfuscation failed");
+/// println!("{}", output.code);
+/// ```
+pub struct Deobfuscator {
+    /// The compiler used for parsing and printing.
+    compiler: swc::Compiler,
+
+    /// The globals the passes run with.
+    globals: Globals,
+
+    /// The handler transforms emit errors to.
+    handler: Handler,
+
+    /// Where the handler writes errors to.
+    err_dst: SharedCursor,
+
+    /// The passes to run, in order.
+    passes: Vec<Pass>,
+
+    /// The input value for [Pass::MathExpr].
+    input: Option<f64>,
+
+    /// The maximum number of rounds to run, if running to a fixed point.
+    max_rounds: Option<usize>,
+
+    /// The ES version the output targets.
+    target: EsVersion,
+
+    /// The name of the script in source maps, if a source map should be emitted.
+    source_map_name: Option<String>
+}
+
+impl Default for Deobfuscator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deobfuscator {
+    /// Constructs a new [Deobfuscator] that runs [Pass::DEFAULT].
+    pub fn new() -> Self {
+        let cm = Arc::<SourceMap>::default();
+        let err_dst = SharedCursor::new();
+        let handler = Handler::with_emitter(
+            false,
+            false,
+            Box::new(EmitterWriter::new(
+                Box::new(err_dst.clone()) as Box<dyn Write + Send>,
+                None,
+                true,
+                false
+            ))
+        );
+
+        Self {
+            compiler: swc::Compiler::new(cm),
+            globals: Globals::new(),
+            handler,
+            err_dst,
+            passes: Pass::DEFAULT.to_vec(),
+            input: None,
+            max_rounds: None,
+            target: EsVersion::latest(),
+            source_map_name: None
+        }
+    }
+
+    /// Replaces the passes to run. Passes run in the given order.
+    pub fn passes(mut self, passes: &[Pass]) -> Self {
+        self.passes = passes.to_vec();
+        self
+    }
+
+    /// Appends `pass` to the passes to run, unless it is already enabled.
+    pub fn enable(mut self, pass: Pass) -> Self {
+        if !self.passes.contains(&pass) {
+            self.passes.push(pass);
+        }
+        self
+    }
+
+    /// Removes `pass` from the passes to run.
+    pub fn disable(mut self, pass: Pass) -> Self {
+        self.passes.retain(|p| *p != pass);
+        self
+    }
+
+    /// Sets the challenge input used by [Pass::MathExpr].
+    pub fn input(mut self, input: f64) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    /// Re-runs the enabled passes until a round no longer changes the AST,
+    /// running at most `max_rounds` rounds.
+    ///
+    /// Without this, each pass runs once, so anything a pass exposes for
+    /// an earlier pass is missed.
+    pub fn fixed_point(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = Some(max_rounds);
+        self
+    }
+
+    /// Sets the ES version the output targets, which is the latest by default.
+    /// The script is always parsed as the latest version.
+    pub fn target(mut self, target: EsVersion) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Emits a v3 source map in [Output::source_map] that maps the output back to
+    /// the script, which is called `source_name` in the map. Nodes the passes create
+    /// keep the span of the code they replace, so the map survives every pass.
+    pub fn emit_source_map(mut self, source_name: impl Into<String>) -> Self {
+        self.source_map_name = Some(source_name.into());
+        self
+    }
+
+    /// The passes that will be run, in order.
+    pub fn enabled_passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    /// The [SourceMap] scripts are loaded into.
+    pub fn source_map(&self) -> &Arc<SourceMap> {
+        &self.compiler.cm
+    }
+
+    /// Parses `code`, runs the enabled passes and prints the result.
+    pub fn deobfuscate(&self, code: String) -> Result<Output, DeobfuscateError> {
+        self.run(code, |fm| self.parse(fm))
+    }
+
+    /// Like [Deobfuscator::deobfuscate], but parses `code` as a single expression,
+    /// like the challenge's function, instead of a script. The output is the same as
+    /// deobfuscating `code` wrapped in parentheses, but spans and source maps point
+    /// into `code` itself.
+    pub fn deobfuscate_expr(&self, code: String) -> Result<Output, DeobfuscateError> {
+        self.run(code, |fm| {
+            // Tokens are captured so that anything after the expression can be found
+            let mut parser = Parser::new_from(Capturing::new(Lexer::new(
+                Syntax::Es(EsConfig::default()),
+                EsVersion::latest(),
+                StringInput::from(&*fm),
+                None
+            )));
+            let result = parser.parse_expr();
+            // Lex the rest of the input, which the parser stops short of
+            parser.input().for_each(drop);
+
+            let mut error = false;
+            for e in parser.take_errors() {
+                e.into_diagnostic(&self.handler).emit();
+                error = true;
+            }
+            let expr = result.map_err(|e| {
+                e.into_diagnostic(&self.handler).emit();
+                anyhow::anyhow!("Syntax Error")
+            })?;
+            if error {
+                return Err(anyhow::anyhow!("Syntax Error"));
+            }
+
+            let span = expr.span();
+            if let Some(token) = parser.input().take().iter().find(|token| token.span.lo >= span.hi) {
+                self.handler.struct_span_err(token.span, "Unexpected input after expression").emit();
+                return Err(anyhow::anyhow!("Syntax Error"));
+            }
+
+            Ok(Program::Script(Script {
+                span,
+                body: vec![Stmt::Expr(ExprStmt {
+                    span,
+                    expr: Box::new(Expr::Paren(ParenExpr { span, expr }))
+                })],
+                shebang: None
+            }))
+        })
+    }
+
+    /// Parses `code` and runs the enabled passes without printing the result, returning
+    /// the answer computed by [Pass::MathExpr]. This is faster than [Deobfuscator::deobfuscate]
+    /// when only the answer is needed.
+    pub fn answer(&self, code: String) -> Result<Option<f64>, DeobfuscateError> {
+        let (_, results, _) = self.transform(code, |fm| self.parse(fm))?;
+
+        Ok(results.answer)
+    }
+
+    /// Parses `fm` as a script.
+    fn parse(&self, fm: Arc<SourceFile>) -> anyhow::Result<Program> {
+        self.compiler.parse_js(
+            fm,
+            &self.handler,
+            EsVersion::latest(), // Who knows what version they target, but this works
+            Syntax::Es(EsConfig::default()),
+            IsModule::Bool(false),
+            None
+        )
+    }
+
+    /// Parses `code` with `parse`, runs the enabled passes and prints the result.
+    fn run(
+        &self,
+        code: String,
+        parse: impl FnOnce(Arc<SourceFile>) -> anyhow::Result<Program>
+    ) -> Result<Output, DeobfuscateError> {
+        let (program, results, fixed_point) = self.transform(code, parse)?;
+
+        let printed = GLOBALS.set(&self.globals, || {
+            // Add back any parentheses the passes made necessary before printing
+            let mut printable = program.clone();
+            printable.visit_mut_with(&mut fixer(None));
+            self.compiler.print(
+                &printable,
+                self.source_map_name.as_deref(),
+                None,
+                true,
+                self.target,
+                SourceMapsConfig::Bool(self.source_map_name.is_some()),
+                &Default::default(),
+                None,
+                false,
+                None,
+                true,
+                false,
+                ""
+            )
+        })
+            .map_err(DeobfuscateError::PrintError)?;
+
+        Ok(Output {
+            code: printed.code,
+            program,
+            answer: results.answer,
+            fixed_point,
+            unresolved_calls: results.unresolved_calls,
+            protections: results.protections,
+            renamed: results.renamed,
+            source_map: printed.map,
+            report: results.report
+        })
+    }
+
+    /// Parses `code` with `parse` and runs the enabled passes.
+    fn transform(
+        &self,
+        code: String,
+        parse: impl FnOnce(Arc<SourceFile>) -> anyhow::Result<Program>
+    ) -> Result<(Program, PassResults, Option<FixedPoint>), DeobfuscateError> {
+        if self.passes.contains(&Pass::MathExpr) && self.input.is_none() {
+            return Err(DeobfuscateError::MissingInput);
+        }
+
+        // Discard errors left over from a previous call
+        self.err_dst.take();
+        self.handler.reset_err_count();
+
+        let fm = self.compiler.cm.new_source_file(FileName::Custom("input.js".into()), code);
+
+        let (program, (results, fixed_point)) = GLOBALS.set(&self.globals, || {
+            let mut program = parse(fm)?;
+
+            let results = self.compiler.run_transform(&self.handler, true, || {
+                self.run_passes(&mut program)
+            });
+
+            Ok::<_, DeobfuscateError>((program, results))
+        })?;
+
+        // Parse emitted errors
+        let errors: Vec<String> = String::from_utf8(self.err_dst.take())?
+            .split('\n')
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect();
+        // Return error if not empty
+        if !errors.is_empty() {
+            return Err(DeobfuscateError::from(errors));
+        }
+
+        Ok((program, results, fixed_point))
+    }
+
+    /// Runs the enabled passes over `program`, once or until a fixed point is reached.
+    /// Returns what the passes found and the rounds that ran.
+    fn run_passes(&self, program: &mut Program) -> (PassResults, Option<FixedPoint>) {
+        let mut results = PassResults::default();
+        let passes = self.pass_order();
+
+        let max_rounds = match self.max_rounds {
+            Some(v) => v,
+            None => {
+                for pass in &passes {
+                    self.run_pass(*pass, program, &mut results);
+                }
+
+                return (results, None);
+            }
+        };
+
+        let mut fixed_point = FixedPoint::default();
+        while fixed_point.rounds.len() < max_rounds {
+            let mut round = Round::default();
+            for pass in &passes {
+                // Spans are ignored as the resolver applies new marks every round
+                let before = program.clone();
+                let mut pass_results = PassResults::default();
+                self.run_pass(*pass, program, &mut pass_results);
+                // A pass that didn't change the AST only finds what it found in an earlier round
+                if !before.eq_ignore_span(program) {
+                    round.changed.push(*pass);
+                    results.append(pass_results);
+                }
+            }
+
+            let changed = !round.changed.is_empty();
+            fixed_point.rounds.push(round);
+            if !changed {
+                fixed_point.converged = true;
+                break;
+            }
+        }
+
+        (results, Some(fixed_point))
+    }
+
+    /// The passes to run, in order. [Pass::Rename] tells bindings apart by the marks
+    /// the resolver applies, so [Pass::Resolver] is added before it if it isn't enabled earlier.
+    fn pass_order(&self) -> Vec<Pass> {
+        let mut passes = self.passes.clone();
+        let resolver = passes.iter().position(|pass| *pass == Pass::Resolver);
+        if let Some(rename) = passes.iter().position(|pass| *pass == Pass::Rename) {
+            if resolver.is_none_or(|resolver| resolver > rename) {
+                passes.insert(rename, Pass::Resolver);
+            }
+        }
+        passes
+    }
+
+    /// Prints `expr` as code.
+    fn print_expr(&self, expr: &Expr) -> String {
+        let mut buf = Vec::new();
+        let mut emitter = Emitter {
+            cfg: Config {
+                target: self.target,
+                ..Default::default()
+            },
+            cm: self.compiler.cm.clone(),
+            comments: None,
+            wr: JsWriter::new(self.compiler.cm.clone(), "\n", &mut buf, None)
+        };
+        expr.emit_with(&mut emitter)
+            .expect("failed to print expression");
+
+        String::from_utf8(buf).expect("printed code isn't UTF-8")
+    }
+
+    /// Runs `pass` over `program`, adding what it found to `results`.
+    fn run_pass(&self, pass: Pass, program: &mut Program, results: &mut PassResults) {
+        match pass {
+            Pass::ExprSimplifier => program.visit_mut_with(
+                &mut expr_simplifier(Mark::new(), Default::default())
+            ),
+            Pass::Resolver => program.visit_mut_with(
+                &mut resolver(Mark::new(), Mark::new(), false)
+            ),
+            Pass::ProxyVars => {
+                let mut visitor = proxy_vars::Visitor::default();
+                program.visit_mut_with(&mut visitor);
+                results.report.proxies.append(&mut visitor.removed);
+            },
+            Pass::Strings => {
+                let mut visitor = strings::Visitor::default();
+                program.visit_mut_with(&mut visitor);
+                results.unresolved_calls.append(&mut visitor.unresolved_calls);
+                results.report.string_arrays.append(&mut visitor.string_arrays);
+            },
+            Pass::StringConcat => program.visit_mut_with(&mut string_concat::Visitor),
+            Pass::ProxyObjects => program.visit_mut_with(
+                &mut proxy_objects::Visitor::default()
+            ),
+            Pass::DeadCode => program.visit_mut_with(
+                &mut dead_code::Visitor::default()
+            ),
+            Pass::ControlFlow => program.visit_mut_with(
+                &mut control_flow::Visitor::default()
+            ),
+            Pass::AntiAnalysis => {
+                let mut visitor = anti_analysis::Visitor::default();
+                program.visit_mut_with(&mut visitor);
+                for protection in visitor.protections {
+                    if !results.protections.contains(&protection) {
+                        results.protections.push(protection);
+                    }
+                }
+            },
+            Pass::ComputedMemberExpr => {
+                let mut visitor = computed_member_expr::Visitor::new(self.target);
+                program.visit_mut_with(&mut visitor);
+                results.report.members.append(&mut visitor.normalised);
+            },
+            Pass::Iife => program.visit_mut_with(&mut iife::Visitor),
+            Pass::Rename => {
+                let mut visitor = rename::Visitor::default();
+                program.visit_mut_with(&mut visitor);
+                results.renamed.append(&mut visitor.renamed);
+            },
+            Pass::MathExpr => {
+                // Checked in deobfuscate
+                let input = self.input.expect("math_expr pass requires an input");
+                let mut visitor = math_expr::Visitor::new(input);
+                program.visit_mut_with(&mut visitor);
+                if visitor.answer.is_some() {
+                    results.answer = visitor.answer;
+                    results.report.answer = visitor.answer;
+                    results.report.math_expression = visitor.expression
+                        .map(|expr| self.print_expr(&expr));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,524 @@
+use std::collections::{HashMap, HashSet};
+use swc_core::common::util::take::Take;
+use swc_core::ecma::ast::{AssignExpr, BinaryOp, Callee, Decl, Expr, Function, Id, Ident, Lit, MemberExpr, MemberProp, ModuleItem, ObjectLit, Pat, PatOrExpr, Program, Prop, PropName, PropOrSpread, ReturnStmt, Stmt, UnaryOp, UpdateExpr, VarDecl, VarDeclarator};
+use swc_core::ecma::atoms::JsWord;
+use swc_core::ecma::visit::{VisitMut, VisitMutWith};
+
+/// Inlines the members of storage objects added by obfuscator.io's control flow flattening.
+///
+/// Example:
+/// ```js
+/// var o = {
+///     'XyZ': function(a, b) {
+///         return a + b;
+///     },
+///     'QrS': 'log'
+/// };
+/// console[o.QrS](o['XyZ'](x, y));
+/// ```
+///
+/// is replaced with:
+///
+/// ```js
+/// console['log'](x + y);
+/// ```
+///
+/// Objects are only inlined if they're never modified or used other than by
+/// reading their members, and are removed once nothing references them.
+/// Function members that aren't trivial aren't inlined, so the object is kept
+/// while they're still called.
+#[derive(Default)]
+pub struct Visitor {
+    /// The members of each storage object.
+    objects: HashMap<Id, HashMap<JsWord, Member>>
+}
+
+/// A member of a storage object.
+#[derive(Clone)]
+enum Member {
+    /// A string or number.
+    Value(Lit),
+
+    /// A function that returns a binary expression, logical expression or call
+    /// using each of its parameters once, in order.
+    Function {
+        /// The function's parameters.
+        params: Vec<Id>,
+
+        /// The returned expression.
+        body: Expr
+    }
+}
+
+impl VisitMut for Visitor {
+    fn visit_mut_program(&mut self, program: &mut Program) {
+        // Find storage objects
+        let mut objects_visitor = FindObjectsVisitor::default();
+        program.visit_mut_children_with(&mut objects_visitor);
+        let mut objects = objects_visitor.objects;
+        objects.retain(|id, _| objects_visitor.declarations.get(id) == Some(&1));
+        if objects.is_empty() {
+            return;
+        }
+
+        // Skip objects that are used for anything other than reading members
+        let mut usage_visitor = UsageVisitor {
+            objects: &objects,
+            unsafe_objects: HashSet::new()
+        };
+        program.visit_mut_children_with(&mut usage_visitor);
+        let unsafe_objects = usage_visitor.unsafe_objects;
+        objects.retain(|id, _| !unsafe_objects.contains(id));
+        self.objects = objects;
+
+        // Inline members
+        program.visit_mut_children_with(self);
+
+        // Remove objects that are no longer referenced
+        let mut references_visitor = ReferencesVisitor {
+            objects: &self.objects,
+            referenced: HashSet::new()
+        };
+        program.visit_mut_children_with(&mut references_visitor);
+        let unused: HashSet<Id> = self.objects
+            .keys()
+            .filter(|id| !references_visitor.referenced.contains(*id))
+            .cloned()
+            .collect();
+        if !unused.is_empty() {
+            let mut remove_visitor = RemoveObjectsVisitor { ids: unused };
+            program.visit_mut_children_with(&mut remove_visitor);
+        }
+    }
+
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        expr.visit_mut_children_with(self);
+
+        match expr {
+            Expr::Member(member) => {
+                if let Some(Member::Value(lit)) = self.get_member(member) {
+                    *expr = Expr::Lit(lit.clone());
+                }
+            },
+            Expr::Call(call) => {
+                let member = match &call.callee {
+                    Callee::Expr(callee) => match &**callee {
+                        Expr::Member(member) => member,
+                        _ => return
+                    },
+                    _ => return
+                };
+                let (params, body) = match self.get_member(member) {
+                    Some(Member::Function { params, body }) => (params, body),
+                    _ => return
+                };
+                if call.args.len() != params.len() || call.args.iter().any(|arg| arg.spread.is_some()) {
+                    return;
+                }
+                // Calling a member directly would change the value of `this`
+                if let Expr::Call(body_call) = body {
+                    if let Callee::Expr(callee) = &body_call.callee {
+                        if let Expr::Ident(callee) = &**callee {
+                            let i = params.iter().position(|param| *param == callee.to_id());
+                            if let Some(Expr::Member(..)) = i.map(|i| &*call.args[i].expr) {
+                                return;
+                            }
+                        }
+                    }
+                }
+
+                // The right side of a logical expression isn't always evaluated, but the
+                // argument is, so it must not have side effects
+                if let Expr::Bin(bin) = body {
+                    let is_logical = matches!(
+                        bin.op,
+                        BinaryOp::LogicalOr | BinaryOp::LogicalAnd | BinaryOp::NullishCoalescing
+                    );
+                    if is_logical && !matches!(&*call.args[1].expr, Expr::Ident(..) | Expr::Lit(..)) {
+                        return;
+                    }
+                }
+
+                // Replace the parameters with the arguments
+                let mut substitute_visitor = SubstituteVisitor {
+                    args: params
+                        .iter()
+                        .cloned()
+                        .zip(call.args.iter().map(|arg| (*arg.expr).clone()))
+                        .collect()
+                };
+                let mut body = body.clone();
+                body.visit_mut_with(&mut substitute_visitor);
+                *expr = body;
+            },
+            _ => {}
+        }
+    }
+}
+
+impl Visitor {
+    /// Gets the storage object member read by `member`.
+    fn get_member(&self, member: &MemberExpr) -> Option<&Member> {
+        let obj = match &*member.obj {
+            Expr::Ident(id) => id,
+            _ => return None
+        };
+        self.objects.get(&obj.to_id())?.get(&get_key(&member.prop)?)
+    }
+}
+
+/// Gets the key read by a member expression, from either `o.key` or `o['key']`.
+fn get_key(prop: &MemberProp) -> Option<JsWord> {
+    match prop {
+        MemberProp::Ident(id) => Some(id.sym.clone()),
+        MemberProp::Computed(computed) => match &*computed.expr {
+            Expr::Lit(Lit::Str(s)) => Some(s.value.clone()),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// Gets the members of a storage object, if `object` is one.
+fn get_members(object: &ObjectLit) -> Option<HashMap<JsWord, Member>> {
+    let mut members = HashMap::new();
+    for prop in &object.props {
+        let key_value = match prop {
+            PropOrSpread::Prop(prop) => match &**prop {
+                Prop::KeyValue(key_value) => key_value,
+                _ => return None
+            },
+            PropOrSpread::Spread(..) => return None
+        };
+        let key = match &key_value.key {
+            PropName::Ident(id) => id.sym.clone(),
+            PropName::Str(s) => s.value.clone(),
+            _ => return None
+        };
+        let member = match &*key_value.value {
+            Expr::Lit(lit @ (Lit::Str(..) | Lit::Num(..))) => Member::Value(lit.clone()),
+            Expr::Fn(fn_expr) => match get_function(&fn_expr.function) {
+                Some(member) => member,
+                // Other functions, like ones calling another object's member,
+                // are left in the object and aren't inlined
+                None => continue
+            },
+            _ => return None
+        };
+        members.insert(key, member);
+    }
+
+    Some(members)
+}
+
+/// Gets the [Member::Function] for `function`, if it's trivial.
+fn get_function(function: &Function) -> Option<Member> {
+    let params = function.params
+        .iter()
+        .map(|param| match &param.pat {
+            Pat::Ident(id) => Some(id.to_id()),
+            _ => None
+        })
+        .collect::<Option<Vec<Id>>>()?;
+
+    let body = match function.body.as_ref()?.stmts.as_slice() {
+        [Stmt::Return(ReturnStmt { arg: Some(arg), .. })] => &**arg,
+        _ => return None
+    };
+    // The operands can only be parameters, in the order they're declared,
+    // so the arguments are still evaluated once and in order
+    let operands: Vec<&Expr> = match body {
+        Expr::Bin(bin) => vec![&bin.left, &bin.right],
+        Expr::Call(call) => {
+            let callee = match &call.callee {
+                Callee::Expr(callee) => &**callee,
+                _ => return None
+            };
+            let mut operands = vec![callee];
+            for arg in &call.args {
+                if arg.spread.is_some() {
+                    return None;
+                }
+                operands.push(&arg.expr);
+            }
+            operands
+        },
+        _ => return None
+    };
+    let operands = operands
+        .into_iter()
+        .map(|operand| match operand {
+            Expr::Ident(id) => Some(id.to_id()),
+            _ => None
+        })
+        .collect::<Option<Vec<Id>>>()?;
+    if operands != params {
+        return None;
+    }
+
+    Some(Member::Function {
+        params,
+        body: body.clone()
+    })
+}
+
+/// Finds objects that could be storage objects.
+#[derive(Default)]
+struct FindObjectsVisitor {
+    /// The members of each object.
+    objects: HashMap<Id, HashMap<JsWord, Member>>,
+
+    /// The number of times each variable is declared.
+    declarations: HashMap<Id, usize>
+}
+
+impl VisitMut for FindObjectsVisitor {
+    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        declarator.visit_mut_children_with(self);
+
+        let id = match &declarator.name {
+            Pat::Ident(id) => id.to_id(),
+            _ => return
+        };
+        *self.declarations.entry(id.clone()).or_default() += 1;
+
+        if let Some(Expr::Object(object)) = declarator.init.as_deref() {
+            if let Some(members) = get_members(object) {
+                self.objects.insert(id, members);
+            }
+        }
+    }
+}
+
+/// Finds objects that are used other than by reading their members.
+struct UsageVisitor<'a> {
+    /// The storage objects.
+    objects: &'a HashMap<Id, HashMap<JsWord, Member>>,
+
+    /// The objects that can't be inlined.
+    unsafe_objects: HashSet<Id>
+}
+
+impl<'a> UsageVisitor<'a> {
+    /// Marks the object read by `expr` as unsafe, if `expr` reads a member of one.
+    fn mark_member(&mut self, expr: &Expr) {
+        if let Expr::Member(member) = expr {
+            if let Expr::Ident(obj) = &*member.obj {
+                if self.objects.contains_key(&obj.to_id()) {
+                    self.unsafe_objects.insert(obj.to_id());
+                }
+            }
+        }
+    }
+}
+
+impl<'a> VisitMut for UsageVisitor<'a> {
+    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        // Skip the name
+        declarator.init.visit_mut_with(self);
+    }
+
+    fn visit_mut_member_expr(&mut self, member: &mut MemberExpr) {
+        if let Expr::Ident(obj) = &*member.obj {
+            if self.objects.contains_key(&obj.to_id()) && get_key(&member.prop).is_some() {
+                // Skip the object
+                member.prop.visit_mut_with(self);
+                return;
+            }
+        }
+        member.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_assign_expr(&mut self, assign: &mut AssignExpr) {
+        assign.visit_mut_children_with(self);
+
+        match &assign.left {
+            PatOrExpr::Expr(expr) => self.mark_member(expr),
+            PatOrExpr::Pat(pat) => {
+                if let Pat::Expr(expr) = &**pat {
+                    self.mark_member(expr);
+                }
+            }
+        }
+    }
+
+    fn visit_mut_update_expr(&mut self, update: &mut UpdateExpr) {
+        update.visit_mut_children_with(self);
+        self.mark_member(&update.arg);
+    }
+
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        expr.visit_mut_children_with(self);
+
+        if let Expr::Unary(unary) = expr {
+            if unary.op == UnaryOp::Delete {
+                self.mark_member(&unary.arg);
+            }
+        }
+    }
+
+    fn visit_mut_ident(&mut self, id: &mut Ident) {
+        // Any other reference
+        if self.objects.contains_key(&id.to_id()) {
+            self.unsafe_objects.insert(id.to_id());
+        }
+    }
+}
+
+/// Replaces parameters with the arguments of a call.
+struct SubstituteVisitor {
+    /// The argument for each parameter.
+    args: HashMap<Id, Expr>
+}
+
+impl VisitMut for SubstituteVisitor {
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        if let Expr::Ident(id) = expr {
+            if let Some(arg) = self.args.get(&id.to_id()) {
+                *expr = arg.clone();
+                return;
+            }
+        }
+        expr.visit_mut_children_with(self);
+    }
+}
+
+/// Finds the storage objects that are still referenced.
+struct ReferencesVisitor<'a> {
+    /// The storage objects.
+    objects: &'a HashMap<Id, HashMap<JsWord, Member>>,
+
+    /// The referenced objects.
+    referenced: HashSet<Id>
+}
+
+impl<'a> VisitMut for ReferencesVisitor<'a> {
+    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        // Skip the name
+        declarator.init.visit_mut_with(self);
+    }
+
+    fn visit_mut_ident(&mut self, id: &mut Ident) {
+        if self.objects.contains_key(&id.to_id()) {
+            self.referenced.insert(id.to_id());
+        }
+    }
+}
+
+/// Removes the declarators of unused storage objects.
+struct RemoveObjectsVisitor {
+    /// The objects to remove.
+    ids: HashSet<Id>
+}
+
+impl VisitMut for RemoveObjectsVisitor {
+    fn visit_mut_var_decl(&mut self, var: &mut VarDecl) {
+        var.decls.retain(|declarator| match &declarator.name {
+            Pat::Ident(id) => !self.ids.contains(&id.to_id()),
+            _ => true
+        });
+    }
+
+    fn visit_mut_stmt(&mut self, stmt: &mut Stmt) {
+        stmt.visit_mut_children_with(self);
+
+        if let Stmt::Decl(Decl::Var(var)) = stmt {
+            if var.decls.is_empty() {
+                stmt.take();
+            }
+        }
+    }
+
+    // Remove empty statements
+    fn visit_mut_stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        stmts.visit_mut_children_with(self);
+
+        stmts.retain(|s| !matches!(s, Stmt::Empty(..)));
+    }
+
+    // Remove empty ModuleItem's
+    fn visit_mut_module_items(&mut self, stmts: &mut Vec<ModuleItem>) {
+        stmts.visit_mut_children_with(self);
+        stmts.retain(|stmt| !matches!(stmt, ModuleItem::Stmt(Stmt::Empty(..))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::deobfuscate::{run_passes, Pass};
+
+    #[test]
+    fn test_inline() {
+        let code = r#"function f(x, y) {
+            var o = {
+                'XyZ': function(a, b) { return a + b; },
+                'AbC': function(a, b) { return a || b; },
+                'DeF': function(a, b, c) { return a(b, c); },
+                QrS: 'log',
+                'TuV': 0x2
+            };
+            console[o.QrS](o['XyZ'](x, y), o['AbC'](x, o.TuV), o['DeF'](g, x, y));
+        }"#;
+
+        assert_eq!(
+            run_passes(code, &[Pass::Resolver, Pass::ProxyObjects]).code,
+            "function f(x, y) {\n    console['log'](x + y, x || 0x2, g(x, y));\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_logical_side_effects() {
+        // `g()` is always called, but wouldn't be if `f()` is truthy once inlined
+        let code = r#"function h() {
+            var o = { 'AbC': function(a, b) { return a || b; } };
+            return o.AbC(f(), g());
+        }"#;
+
+        let output = run_passes(code, &[Pass::Resolver, Pass::ProxyObjects]).code;
+        assert!(output.contains("return o.AbC(f(), g());"));
+        assert!(!output.contains("f() || g()"));
+    }
+
+    #[test]
+    fn test_modified_object() {
+        // A member is replaced, so the object isn't inlined
+        let code = r#"var o = { 'QrS': 'log' }; o.QrS = 'warn'; console[o.QrS]();"#;
+
+        assert!(run_passes(code, &[Pass::Resolver, Pass::ProxyObjects]).code.contains("console[o.QrS]()"));
+    }
+
+    #[test]
+    fn test_referenced_object() {
+        // The function member is passed on, so the object is kept
+        let code = r#"var o = { 'XyZ': function(a, b) { return a + b; }, 'QrS': 'log' };
+            console[o.QrS](o.XyZ);"#;
+
+        let output = run_passes(code, &[Pass::Resolver, Pass::ProxyObjects]).code;
+        assert!(output.contains("var o ="));
+        assert!(output.contains("console['log'](o.XyZ)"));
+    }
+
+    #[test]
+    fn test_non_trivial_member() {
+        // A member calls another object's member, so it's left in the object
+        let code = r#"function f(x, y) {
+            var p = { 'XyZ': function(a, b) { return a + b; } };
+            var o = {
+                'AbC': function(a, b) { return p.XyZ(a, b); },
+                'QrS': 'log'
+            };
+            console[o.QrS](o.AbC(x, y));
+        }"#;
+
+        assert_eq!(run_passes(code, &[Pass::Resolver, Pass::ProxyObjects]).code, r#"function f(x, y) {
+    var o = {
+        'AbC': function(a, b) {
+            return a + b;
+        },
+        'QrS': 'log'
+    };
+    console['log'](o.AbC(x, y));
+}
+"#);
+    }
+}
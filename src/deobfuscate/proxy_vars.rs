@@ -1,9 +1,8 @@
 use std::collections::{HashMap, HashSet};
-use std::default::Default;
 use swc_core::common::SyntaxContext;
 use swc_core::common::util::take::Take;
 use swc_core::ecma::visit::{VisitMut, VisitMutWith};
-use swc_core::ecma::ast::{Decl, Expr, FnDecl, Id, Ident, ModuleItem, Pat, Program, Stmt, VarDeclarator};
+use swc_core::ecma::ast::{ArrowExpr, AssignExpr, AssignOp, BlockStmt, BlockStmtOrExpr, BreakStmt, CatchClause, ClassDecl, ClassExpr, Constructor, ContinueStmt, Decl, ExportDecl, ExportNamedSpecifier, ExportSpecifier, Expr, FnDecl, FnExpr, ForHead, ForInStmt, ForOfStmt, ForStmt, Function, GetterProp, Ident, ImportDefaultSpecifier, ImportNamedSpecifier, ImportStarAsSpecifier, KeyValueProp, LabeledStmt, MemberProp, ModuleExportName, ModuleItem, NamedExport, ObjectPatProp, ParamOrTsParamProp, Pat, PatOrExpr, Program, Prop, PropName, ReturnStmt, SetterProp, Stmt, SuperProp, SwitchStmt, UpdateExpr, VarDecl, VarDeclKind, VarDeclarator, VarDeclOrExpr};
 use swc_core::ecma::atoms::JsWord;
 
 /// Replaces proxy variables with references to the real variable.
@@ -25,28 +24,28 @@ use swc_core::ecma::atoms::JsWord;
 ///     return doStuff();
 /// }
 /// ```
+///
+/// Chains of aliases (`var t = a; var a = doStuff;`) are followed to the function,
+/// which can be a FunctionDeclaration that isn't reassigned, except by obfuscator.io's
+/// string array functions replacing themselves, or a variable that's only assigned a
+/// function expression. Aliases that are reassigned or exported are left alone.
+///
+/// Bindings are found with a scope tree built by [ScopeVisitor], so this pass
+/// doesn't depend on the resolver having run first.
 #[derive(Default)]
 pub struct Visitor {
-    /// Function identifiers obtained from [FunctionVisitor].
-    /// Used to check if an [Id] is a FunctionDeclaration.
-    functions: HashSet<Id>,
-
-    /// A map of [JsWord]'s along with the lowest [SyntaxContext].
-    /// This is used for checking if an identifier's name exists
-    /// in an upper scope to avoid name collisions.
-    identifiers: HashMap<JsWord, SyntaxContext>,
-
-    /// Variable replacements. The key is the variable [Id],
-    /// and the value is the [JsWord] of the FunctionDeclaration
-    /// being called.
-    replacements: HashMap<Id, Ident>,
+    /// The proxy variables that were removed, in the order they're declared.
+    pub removed: Vec<RemovedProxy>
+}
 
-    /// Counter for generating unique names.
-    new_name_counter: usize,
+/// A proxy variable that [Visitor] removed.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RemovedProxy {
+    /// The name of the proxy variable.
+    pub name: String,
 
-    /// Function identifier replacements.
-    /// The key is the current function name, and the value is the new name.
-    function_replacements: HashMap<Id, JsWord>
+    /// The name of the function it was replaced with.
+    pub target: String
 }
 
 impl VisitMut for Visitor {
@@ -57,96 +56,805 @@ impl VisitMut for Visitor {
         var r = c;
         function c() {}
 
-        We have to run a separate visitor first to get all the functions,
-        instead of just traversing down.
+        We have to build the scope tree first, instead of just traversing down.
          */
-        let mut fn_visitor = FunctionVisitor::default();
-        program.visit_mut_children_with(&mut fn_visitor);
-        self.functions = fn_visitor.functions;
-        self.identifiers = fn_visitor.identifiers;
-
-        // Replace identifiers and remove variables
-        program.visit_mut_children_with(self);
-
-        // Rename functions that were marked for renaming due to collisions
-        if !self.function_replacements.is_empty() {
-            let mut renamer = RenameFunctionVisitor {
-                replacements: self.function_replacements.clone()
+        let mut scope_visitor = ScopeVisitor::new();
+        program.visit_mut_children_with(&mut scope_visitor);
+        let ScopeVisitor { tree, references, aliases: candidates, writes, functions, memoized, exports, .. } = scope_visitor;
+
+        // Resolve every reference to the binding it refers to
+        let bindings: Vec<Option<Binding>> = references
+            .iter()
+            .map(|reference| tree.resolve(reference.scope, &reference.sym))
+            .collect();
+        let resolve_all = |references: Vec<Reference>| references
+            .into_iter()
+            .filter_map(|reference| tree.resolve(reference.scope, &reference.sym).map(|binding| (binding, reference.scope)));
+
+        // The scopes each binding is written in, including declarators with an init
+        let mut write_scopes: HashMap<Binding, Vec<ScopeId>> = HashMap::new();
+        for (binding, scope) in resolve_all(writes) {
+            write_scopes.entry(binding).or_default().push(scope);
+        }
+        let write_count = |binding: &Binding| write_scopes.get(binding).map_or(0, Vec::len);
+        let functions: HashSet<Binding> = resolve_all(functions).map(|(binding, _)| binding).collect();
+        let memoized: HashSet<Binding> = resolve_all(memoized).map(|(binding, _)| binding).collect();
+        let exports: HashSet<Binding> = resolve_all(exports).map(|(binding, _)| binding).collect();
+
+        // If `binding` always holds the same function.
+        // The string array getter reassigns itself to a function that returns
+        // the same thing, which is fine if that's the only time it's written.
+        let is_function = |binding: &Binding| match tree.kind(binding) {
+            BindingKind::Function(scope) => match write_scopes.get(binding).map(Vec::as_slice) {
+                None | Some([]) => true,
+                Some([write]) => memoized.contains(binding) && tree.is_ancestor(scope, *write),
+                Some(_) => false
+            },
+            BindingKind::Var => functions.contains(binding) && write_count(binding) == 1,
+            BindingKind::Other => false
+        };
+
+        /*
+        Find the aliases: variables that are only ever assigned another variable.
+
+        Parameters are never aliases since they're written by the caller, which is
+        also why `arguments` can't be used to modify one: a variable that's declared
+        with the same name as a parameter is the same binding, so it isn't a [BindingKind::Var].
+         */
+        let mut aliases: HashMap<Binding, Binding> = HashMap::new();
+        for &(name, init) in &candidates {
+            let (Some(alias), Some(target)) = (bindings[name].clone(), bindings[init].clone()) else {
+                continue;
+            };
+            if alias != target
+                && tree.kind(&alias) == BindingKind::Var
+                && write_count(&alias) == 1
+                && !exports.contains(&alias) {
+                aliases.insert(alias, target);
+            }
+        }
+
+        // Follow each alias to the function it ends up referencing
+        let mut proxies: HashMap<Binding, Binding> = HashMap::new();
+        for alias in aliases.keys() {
+            let mut target = alias;
+            let mut steps = 0;
+            while let Some(next) = aliases.get(target) {
+                target = next;
+                steps += 1;
+                // Aliases of each other
+                if steps > aliases.len() {
+                    break;
+                }
+            }
+            if steps <= aliases.len() && is_function(target) {
+                proxies.insert(alias.clone(), target.clone());
+            }
+        }
+
+        /*
+        Check that the function can be referenced wherever the proxy is used.
+
+        The replacement, in most cases, is the function's name, but there is
+        a special case we MUST handle to avoid breaking code.
+        Observe the following code:
+
+        var r = c;
+        function c() {}
+        function doStuff(c) {
+            r();
+        }
+
+        This looks normal at first, but the problem here is if we replace
+        r() with c(), then we'll be calling the parameter passed into the
+        function instead of the "c" function in the upper scope.
+        To avoid this, we resolve the function's name from the scope of each
+        use, and rename the function if it resolves to a different binding.
+        If the function isn't visible there at all, the proxy is kept.
+         */
+        let mut shadowed = HashSet::new();
+        for (reference, binding) in references.iter().zip(&bindings) {
+            let Some(target) = binding.as_ref().and_then(|binding| proxies.get(binding)).cloned() else {
+                continue;
+            };
+            if !tree.is_ancestor(target.0, reference.scope) {
+                proxies.remove(binding.as_ref().unwrap());
+            } else if tree.resolve(reference.scope, &target.1).as_ref() != Some(&target) {
+                shadowed.insert(target);
+            }
+        }
+
+        // Rename functions that are shadowed to names that aren't used anywhere
+        let used_names: HashSet<&JsWord> = references.iter().map(|reference| &reference.sym).collect();
+        let mut new_name_counter = 0;
+        let mut function_replacements = HashMap::new();
+        for target in shadowed {
+            // Only rename functions that still have a proxy
+            if !proxies.values().any(|proxy_target| *proxy_target == target) {
+                continue;
+            }
+            let new_name = loop {
+                let new_name = JsWord::from(format!("proxyFn{}", new_name_counter));
+                new_name_counter += 1;
+                if !used_names.contains(&new_name) {
+                    break new_name;
+                }
+            };
+            function_replacements.insert(target, new_name);
+        }
+
+        // Replace references to proxies with the function, and rename functions
+        let mut replacements = HashMap::new();
+        for (index, binding) in bindings.iter().enumerate() {
+            let Some(binding) = binding else {
+                continue;
+            };
+            let target = proxies.get(binding).unwrap_or(binding);
+            if proxies.contains_key(binding) || function_replacements.contains_key(target) {
+                let sym = function_replacements.get(target).unwrap_or(&target.1).clone();
+                replacements.insert(index, (sym, tree.ctxt(target)));
+            }
+        }
+        let mut declarators = HashSet::new();
+        for &(name, _) in &candidates {
+            let Some((binding, target)) = bindings[name].as_ref().and_then(|binding| proxies.get_key_value(binding)) else {
+                continue;
             };
-            program.visit_mut_children_with(&mut renamer);
+            declarators.insert(name);
+            self.removed.push(RemovedProxy {
+                name: binding.1.to_string(),
+                target: function_replacements.get(target).unwrap_or(&target.1).to_string()
+            });
+        }
+
+        let mut replace_visitor = ReplaceVisitor {
+            replacements,
+            declarators,
+            index: 0
+        };
+        program.visit_mut_children_with(&mut replace_visitor);
+        debug_assert_eq!(replace_visitor.index, references.len());
+    }
+}
+
+/// Index of a [Scope] in [ScopeTree].
+type ScopeId = usize;
+
+/// A binding, identified by the scope it's declared in and its name.
+type Binding = (ScopeId, JsWord);
+
+/// What a binding was declared as.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum BindingKind {
+    /// A FunctionDeclaration, with the scope of its body.
+    Function(ScopeId),
+
+    /// A `var`, `let` or `const` variable.
+    Var,
+
+    /// Anything else, or a name that's declared as more than one kind.
+    Other
+}
+
+/// A lexical scope.
+struct Scope {
+    /// The enclosing scope, or [None] for the program.
+    parent: Option<ScopeId>,
+
+    /// If this is the scope of a function (or the program),
+    /// which is where `var` declarations go.
+    is_function: bool,
+
+    /// The names declared in this scope, with their kind and [SyntaxContext].
+    bindings: HashMap<JsWord, (BindingKind, SyntaxContext)>
+}
+
+/// The scopes of a program.
+struct ScopeTree {
+    scopes: Vec<Scope>
+}
+
+impl ScopeTree {
+    /// Adds a scope and returns its [ScopeId].
+    fn add(&mut self, parent: Option<ScopeId>, is_function: bool) -> ScopeId {
+        self.scopes.push(Scope {
+            parent,
+            is_function,
+            bindings: HashMap::new()
+        });
+        self.scopes.len() - 1
+    }
+
+    /// Declares `ident` in `scope`.
+    fn declare(&mut self, scope: ScopeId, ident: &Ident, kind: BindingKind) {
+        self.scopes[scope].bindings
+            .entry(ident.sym.clone())
+            .and_modify(|(existing, _)| if *existing != kind {
+                *existing = BindingKind::Other;
+            })
+            .or_insert((kind, ident.span.ctxt));
+    }
+
+    /// Returns the binding `sym` refers to when used in `scope`,
+    /// or [None] if it's a global.
+    fn resolve(&self, mut scope: ScopeId, sym: &JsWord) -> Option<Binding> {
+        loop {
+            if self.scopes[scope].bindings.contains_key(sym) {
+                return Some((scope, sym.clone()));
+            }
+            scope = self.scopes[scope].parent?;
+        }
+    }
+
+    /// If `ancestor` is `scope` or one of its enclosing scopes.
+    fn is_ancestor(&self, ancestor: ScopeId, mut scope: ScopeId) -> bool {
+        loop {
+            if scope == ancestor {
+                return true;
+            }
+            match self.scopes[scope].parent {
+                Some(parent) => scope = parent,
+                None => return false
+            }
+        }
+    }
+
+    /// Returns the innermost function scope containing `scope`.
+    fn function_scope(&self, mut scope: ScopeId) -> ScopeId {
+        while !self.scopes[scope].is_function {
+            scope = self.scopes[scope].parent.unwrap();
+        }
+        scope
+    }
+
+    /// Returns what `binding` was declared as.
+    fn kind(&self, binding: &Binding) -> BindingKind {
+        self.scopes[binding.0].bindings[&binding.1].0
+    }
+
+    /// Returns the [SyntaxContext] of `binding`'s declaration.
+    fn ctxt(&self, binding: &Binding) -> SyntaxContext {
+        self.scopes[binding.0].bindings[&binding.1].1
+    }
+}
+
+/// A reference to a name, which is any identifier that isn't a property name or label.
+struct Reference {
+    /// The name.
+    sym: JsWord,
+
+    /// The scope the name is used in.
+    scope: ScopeId
+}
+
+/// If `fn_decl` is shaped like obfuscator.io's string array functions, which
+/// replace themselves on their first call:
+/// ```js
+/// function c() {
+///     var e = [...];
+///     return c = function() {
+///         return e;
+///     }, c();
+/// }
+/// ```
+fn is_memoized(fn_decl: &FnDecl) -> bool {
+    let Some([decls @ .., Stmt::Return(ReturnStmt { arg: Some(arg), .. })]) = fn_decl.function.body
+        .as_ref()
+        .map(|body| body.stmts.as_slice()) else {
+        return false;
+    };
+    if !decls.iter().all(|stmt| matches!(stmt, Stmt::Decl(Decl::Var(..)))) {
+        return false;
+    }
+    let Expr::Seq(seq) = &**arg else {
+        return false;
+    };
+    let [assign, call] = seq.exprs.as_slice() else {
+        return false;
+    };
+    let is_name = |expr: &Expr| matches!(expr, Expr::Ident(ident) if ident.sym == fn_decl.ident.sym);
+
+    let is_redefinition = match &**assign {
+        Expr::Assign(assign) => assign.op == AssignOp::Assign
+            && assign.left.as_ident().is_some_and(|ident| ident.sym == fn_decl.ident.sym)
+            && matches!(&*assign.right, Expr::Fn(..)),
+        _ => false
+    };
+    let is_call = match &**call {
+        Expr::Call(call) => call.callee.as_expr().is_some_and(|callee| is_name(callee)),
+        _ => false
+    };
+    is_redefinition && is_call
+}
+
+/// Adds the identifiers bound by `pat` to `idents`.
+fn pat_idents<'a>(pat: &'a Pat, idents: &mut Vec<&'a Ident>) {
+    match pat {
+        Pat::Ident(id) => idents.push(&id.id),
+        Pat::Array(array) => {
+            for elem in array.elems.iter().flatten() {
+                pat_idents(elem, idents);
+            }
+        },
+        Pat::Rest(rest) => pat_idents(&rest.arg, idents),
+        Pat::Object(object) => {
+            for prop in &object.props {
+                match prop {
+                    ObjectPatProp::KeyValue(kv) => pat_idents(&kv.value, idents),
+                    ObjectPatProp::Assign(assign) => idents.push(&assign.key),
+                    ObjectPatProp::Rest(rest) => pat_idents(&rest.arg, idents)
+                }
+            }
+        },
+        Pat::Assign(assign) => pat_idents(&assign.left, idents),
+        Pat::Expr(expr) => {
+            // Only in assignments, like `[a.b, c] = d`
+            if let Expr::Ident(id) = &**expr {
+                idents.push(id);
+            }
+        },
+        Pat::Invalid(..) => {}
+    }
+}
+
+/// Builds the [ScopeTree] and finds every [Reference] and proxy candidate.
+struct ScopeVisitor {
+    /// The scopes found so far.
+    tree: ScopeTree,
+
+    /// The scope being visited.
+    current: ScopeId,
+
+    /// Every reference, in the order they're visited.
+    references: Vec<Reference>,
+
+    /// Declarators like `var r = c`, as the indexes of the
+    /// name and init in `references`.
+    aliases: Vec<(usize, usize)>,
+
+    /// Names that are written to, including declarators with an init.
+    writes: Vec<Reference>,
+
+    /// Variables declared with a function expression as their init.
+    functions: Vec<Reference>,
+
+    /// FunctionDeclarations that memoize themselves, see [is_memoized].
+    memoized: Vec<Reference>,
+
+    /// Names exported from the module.
+    exports: Vec<Reference>
+}
+
+impl ScopeVisitor {
+    fn new() -> Self {
+        let mut tree = ScopeTree { scopes: Vec::new() };
+        let current = tree.add(None, true);
+        Self {
+            tree,
+            current,
+            references: Vec::new(),
+            aliases: Vec::new(),
+            writes: Vec::new(),
+            functions: Vec::new(),
+            memoized: Vec::new(),
+            exports: Vec::new()
+        }
+    }
+
+    /// Enters a new scope and returns the previous one, which must be restored after.
+    fn enter(&mut self, is_function: bool) -> ScopeId {
+        let previous = self.current;
+        self.current = self.tree.add(Some(previous), is_function);
+        previous
+    }
+
+    /// Declares the identifiers bound by `pat` in `scope`.
+    fn declare_pat(&mut self, scope: ScopeId, pat: &Pat) {
+        let mut idents = Vec::new();
+        pat_idents(pat, &mut idents);
+        for ident in idents {
+            self.tree.declare(scope, ident, BindingKind::Other);
+        }
+    }
+
+    /// Returns a [Reference] to each identifier bound by `pat`.
+    fn pat_references(&self, pat: &Pat) -> Vec<Reference> {
+        let mut idents = Vec::new();
+        pat_idents(pat, &mut idents);
+        idents
+            .into_iter()
+            .map(|ident| Reference {
+                sym: ident.sym.clone(),
+                scope: self.current
+            })
+            .collect()
+    }
+
+    /// Adds the names a `for...in` or `for...of` loop writes to on each iteration.
+    fn add_loop_writes(&mut self, left: &ForHead) {
+        let writes: Vec<Reference> = match left {
+            ForHead::VarDecl(var) => var.decls
+                .iter()
+                .flat_map(|declarator| self.pat_references(&declarator.name))
+                .collect(),
+            ForHead::UsingDecl(using) => using.decls
+                .iter()
+                .flat_map(|declarator| self.pat_references(&declarator.name))
+                .collect(),
+            ForHead::Pat(pat) => self.pat_references(pat)
+        };
+        self.writes.extend(writes);
+    }
+
+    /// Visits a function body without giving it its own block scope.
+    fn visit_body(&mut self, body: &mut Option<BlockStmt>) {
+        if let Some(body) = body {
+            body.stmts.visit_mut_with(self);
+        }
+    }
+}
+
+impl VisitMut for ScopeVisitor {
+    fn visit_mut_function(&mut self, function: &mut Function) {
+        let previous = self.enter(true);
+        for param in &function.params {
+            self.declare_pat(self.current, &param.pat);
+        }
+        function.params.visit_mut_with(self);
+        function.decorators.visit_mut_with(self);
+        self.visit_body(&mut function.body);
+        self.current = previous;
+    }
+
+    fn visit_mut_arrow_expr(&mut self, arrow: &mut ArrowExpr) {
+        let previous = self.enter(true);
+        for param in &arrow.params {
+            self.declare_pat(self.current, param);
+        }
+        arrow.params.visit_mut_with(self);
+        match &mut *arrow.body {
+            BlockStmtOrExpr::BlockStmt(body) => body.stmts.visit_mut_with(self),
+            BlockStmtOrExpr::Expr(expr) => expr.visit_mut_with(self)
+        }
+        self.current = previous;
+    }
+
+    fn visit_mut_constructor(&mut self, constructor: &mut Constructor) {
+        constructor.key.visit_mut_with(self);
+        let previous = self.enter(true);
+        for param in &constructor.params {
+            if let ParamOrTsParamProp::Param(param) = param {
+                self.declare_pat(self.current, &param.pat);
+            }
+        }
+        constructor.params.visit_mut_with(self);
+        self.visit_body(&mut constructor.body);
+        self.current = previous;
+    }
+
+    fn visit_mut_getter_prop(&mut self, getter: &mut GetterProp) {
+        getter.key.visit_mut_with(self);
+        let previous = self.enter(true);
+        self.visit_body(&mut getter.body);
+        self.current = previous;
+    }
+
+    fn visit_mut_setter_prop(&mut self, setter: &mut SetterProp) {
+        setter.key.visit_mut_with(self);
+        let previous = self.enter(true);
+        self.declare_pat(self.current, &setter.param);
+        setter.param.visit_mut_with(self);
+        self.visit_body(&mut setter.body);
+        self.current = previous;
+    }
+
+    fn visit_mut_fn_decl(&mut self, fn_decl: &mut FnDecl) {
+        // The function's scope is the next one to be added
+        let scope = self.tree.scopes.len();
+        self.tree.declare(self.current, &fn_decl.ident, BindingKind::Function(scope));
+        if is_memoized(fn_decl) {
+            self.memoized.push(Reference {
+                sym: fn_decl.ident.sym.clone(),
+                scope: self.current
+            });
+        }
+        fn_decl.visit_mut_children_with(self);
+    }
+
+    // A named function expression's name is only visible inside it
+    fn visit_mut_fn_expr(&mut self, fn_expr: &mut FnExpr) {
+        if let Some(ident) = &fn_expr.ident {
+            let previous = self.enter(false);
+            self.tree.declare(self.current, ident, BindingKind::Other);
+            fn_expr.visit_mut_children_with(self);
+            self.current = previous;
+        } else {
+            fn_expr.visit_mut_children_with(self);
+        }
+    }
+
+    fn visit_mut_class_decl(&mut self, class_decl: &mut ClassDecl) {
+        self.tree.declare(self.current, &class_decl.ident, BindingKind::Other);
+        class_decl.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_class_expr(&mut self, class_expr: &mut ClassExpr) {
+        if let Some(ident) = &class_expr.ident {
+            let previous = self.enter(false);
+            self.tree.declare(self.current, ident, BindingKind::Other);
+            class_expr.visit_mut_children_with(self);
+            self.current = previous;
+        } else {
+            class_expr.visit_mut_children_with(self);
+        }
+    }
+
+    fn visit_mut_var_decl(&mut self, var: &mut VarDecl) {
+        // `var` declarations are hoisted to the function
+        let scope = if var.kind == VarDeclKind::Var {
+            self.tree.function_scope(self.current)
+        } else {
+            self.current
+        };
+        for declarator in &var.decls {
+            let mut idents = Vec::new();
+            pat_idents(&declarator.name, &mut idents);
+            for ident in idents {
+                self.tree.declare(scope, ident, BindingKind::Var);
+            }
         }
+        var.visit_mut_children_with(self);
     }
 
     fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        if let Some(init) = &declarator.init {
+            let writes = self.pat_references(&declarator.name);
+            if let Pat::Ident(..) = &declarator.name {
+                match &**init {
+                    Expr::Ident(..) => {
+                        let name = self.references.len();
+                        self.aliases.push((name, name + 1));
+                    },
+                    Expr::Fn(..) | Expr::Arrow(..) => self.functions.extend(self.pat_references(&declarator.name)),
+                    _ => {}
+                }
+            }
+            self.writes.extend(writes);
+        }
         declarator.visit_mut_children_with(self);
+    }
 
-        // Is the declarator's name an identifier?
-        if let Pat::Ident(var_id) = &declarator.name {
-            // Is init an identifier?
-            if let Some(expr) = &declarator.init {
-                if let Expr::Ident(fn_id) = &**expr {
-                    // Is the identifier a FunctionDeclaration?
-                    if self.functions.contains(&fn_id.to_id()) {
-                        /*
-                        Get the replacement JsWord.
-
-                        The replacement, in most cases, is `id.sym`, but there is
-                        a special case we MUST handle to avoid breaking code.
-                        Observe the following code:
-
-                        var r = c;
-                        function c() {}
-                        function doStuff(c) {
-                            r();
-                        }
-
-                        This looks normal at first, but the problem here is if we replace
-                        r() with c(), then we'll be calling the parameter passed into the
-                        function instead of the "c" function in the upper scope.
-                        To avoid this, we have a map of JsWord -> SyntaxContext, each
-                        value being the lowest SyntaxContext. We can check if a variable
-                        of the same name exists in the upper scope by comparing the lowest
-                        context (lowest_ctx) with the syntax context of the real function
-                        being called (fn_id.span.ctxt).
-                         */
-                        let replacement_sym = if let Some(highest_ctx) = self.identifiers.get(&fn_id.sym) {
-                            if fn_id.span.ctxt < *highest_ctx {
-                                // Generate new name
-                                let mut new_name = String::from("proxyFn");
-                                new_name.push_str(self.new_name_counter.to_string().as_str());
-                                self.new_name_counter += 1;
-
-                                // Set function replacement
-                                let replacement = JsWord::from(new_name);
-                                self.function_replacements.insert(fn_id.to_id(), replacement.clone());
-
-                                replacement
-                            } else {
-                                fn_id.sym.clone()
-                            }
-                        } else {
-                            fn_id.sym.clone()
-                        };
-
-                        // Add a replacement
-                        self.replacements.insert(var_id.to_id(), Ident {
-                            span: fn_id.span.clone(),
-                            sym: replacement_sym,
-                            optional: false,
-                        });
-                        // Mark declarator for deletion
-                        declarator.name.take();
-                    }
+    fn visit_mut_assign_expr(&mut self, assign: &mut AssignExpr) {
+        match &assign.left {
+            PatOrExpr::Pat(pat) => {
+                let writes = self.pat_references(pat);
+                self.writes.extend(writes);
+            },
+            PatOrExpr::Expr(expr) => {
+                if let Expr::Ident(ident) = &**expr {
+                    self.writes.push(Reference {
+                        sym: ident.sym.clone(),
+                        scope: self.current
+                    });
                 }
             }
         }
+        assign.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_update_expr(&mut self, update: &mut UpdateExpr) {
+        if let Expr::Ident(ident) = &*update.arg {
+            self.writes.push(Reference {
+                sym: ident.sym.clone(),
+                scope: self.current
+            });
+        }
+        update.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_export_decl(&mut self, export: &mut ExportDecl) {
+        if let Decl::Var(var) = &export.decl {
+            for declarator in &var.decls {
+                let exports = self.pat_references(&declarator.name);
+                self.exports.extend(exports);
+            }
+        }
+        export.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_named_export(&mut self, export: &mut NamedExport) {
+        // Re-exports from another module don't reference anything here
+        if export.src.is_none() {
+            for specifier in &export.specifiers {
+                if let ExportSpecifier::Named(ExportNamedSpecifier { orig: ModuleExportName::Ident(ident), .. }) = specifier {
+                    self.exports.push(Reference {
+                        sym: ident.sym.clone(),
+                        scope: self.current
+                    });
+                }
+            }
+        }
+        export.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_block_stmt(&mut self, block: &mut BlockStmt) {
+        let previous = self.enter(false);
+        block.visit_mut_children_with(self);
+        self.current = previous;
+    }
+
+    fn visit_mut_catch_clause(&mut self, catch: &mut CatchClause) {
+        let previous = self.enter(false);
+        if let Some(param) = &catch.param {
+            self.declare_pat(self.current, param);
+        }
+        catch.visit_mut_children_with(self);
+        self.current = previous;
+    }
+
+    fn visit_mut_for_stmt(&mut self, for_stmt: &mut ForStmt) {
+        let previous = self.enter(false);
+        for_stmt.visit_mut_children_with(self);
+        self.current = previous;
+    }
+
+    fn visit_mut_for_in_stmt(&mut self, for_in: &mut ForInStmt) {
+        let previous = self.enter(false);
+        self.add_loop_writes(&for_in.left);
+        for_in.visit_mut_children_with(self);
+        self.current = previous;
+    }
+
+    fn visit_mut_for_of_stmt(&mut self, for_of: &mut ForOfStmt) {
+        let previous = self.enter(false);
+        self.add_loop_writes(&for_of.left);
+        for_of.visit_mut_children_with(self);
+        self.current = previous;
+    }
+
+    fn visit_mut_switch_stmt(&mut self, switch: &mut SwitchStmt) {
+        switch.discriminant.visit_mut_with(self);
+        let previous = self.enter(false);
+        switch.cases.visit_mut_with(self);
+        self.current = previous;
+    }
+
+    fn visit_mut_import_named_specifier(&mut self, specifier: &mut ImportNamedSpecifier) {
+        self.tree.declare(self.current, &specifier.local, BindingKind::Other);
+        specifier.local.visit_mut_with(self);
+    }
+
+    fn visit_mut_import_default_specifier(&mut self, specifier: &mut ImportDefaultSpecifier) {
+        self.tree.declare(self.current, &specifier.local, BindingKind::Other);
+        specifier.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_import_star_as_specifier(&mut self, specifier: &mut ImportStarAsSpecifier) {
+        self.tree.declare(self.current, &specifier.local, BindingKind::Other);
+        specifier.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_ident(&mut self, ident: &mut Ident) {
+        self.references.push(Reference {
+            sym: ident.sym.clone(),
+            scope: self.current
+        });
+    }
+
+    // Names that aren't references. These must match [ReplaceVisitor].
+
+    fn visit_mut_member_prop(&mut self, prop: &mut MemberProp) {
+        if let MemberProp::Computed(..) = prop {
+            prop.visit_mut_children_with(self);
+        }
+    }
+
+    fn visit_mut_super_prop(&mut self, prop: &mut SuperProp) {
+        if let SuperProp::Computed(..) = prop {
+            prop.visit_mut_children_with(self);
+        }
     }
 
-    // Replace identifiers with their replacement.
+    fn visit_mut_prop_name(&mut self, name: &mut PropName) {
+        if let PropName::Computed(..) = name {
+            name.visit_mut_children_with(self);
+        }
+    }
+
+    fn visit_mut_labeled_stmt(&mut self, labeled: &mut LabeledStmt) {
+        labeled.body.visit_mut_with(self);
+    }
+
+    fn visit_mut_break_stmt(&mut self, _: &mut BreakStmt) {}
+
+    fn visit_mut_continue_stmt(&mut self, _: &mut ContinueStmt) {}
+
+    fn visit_mut_export_named_specifier(&mut self, specifier: &mut ExportNamedSpecifier) {
+        specifier.orig.visit_mut_with(self);
+    }
+}
+
+/// Replaces references found by [ScopeVisitor] and removes proxy declarators.
+/// Identifiers are matched by the order they're visited in, so this must skip
+/// the same names [ScopeVisitor] does.
+struct ReplaceVisitor {
+    /// The new name and [SyntaxContext] of references, by their index.
+    replacements: HashMap<usize, (JsWord, SyntaxContext)>,
+
+    /// Declarators to remove, by the index of their name.
+    declarators: HashSet<usize>,
+
+    /// The index of the next reference.
+    index: usize
+}
+
+impl VisitMut for ReplaceVisitor {
     fn visit_mut_ident(&mut self, ident: &mut Ident) {
-        if let Some(new_id) = self.replacements.get(&ident.to_id()) {
-            *ident = new_id.clone();
+        if let Some((sym, ctxt)) = self.replacements.get(&self.index) {
+            ident.sym = sym.clone();
+            ident.span.ctxt = *ctxt;
+        }
+        self.index += 1;
+    }
+
+    fn visit_mut_prop(&mut self, prop: &mut Prop) {
+        // `{ r }` becomes `{ r: c }` so the key stays the same
+        if let Prop::Shorthand(ident) = prop {
+            if self.replacements.contains_key(&self.index) {
+                let mut value = ident.clone();
+                self.visit_mut_ident(&mut value);
+                *prop = Prop::KeyValue(KeyValueProp {
+                    key: PropName::Ident(ident.clone()),
+                    value: Box::new(Expr::Ident(value))
+                });
+                return;
+            }
         }
+        prop.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        let index = self.index;
+        declarator.visit_mut_children_with(self);
+
+        // Mark declarator for deletion
+        if self.declarators.contains(&index) {
+            declarator.name.take();
+        }
+    }
+
+    fn visit_mut_member_prop(&mut self, prop: &mut MemberProp) {
+        if let MemberProp::Computed(..) = prop {
+            prop.visit_mut_children_with(self);
+        }
+    }
+
+    fn visit_mut_super_prop(&mut self, prop: &mut SuperProp) {
+        if let SuperProp::Computed(..) = prop {
+            prop.visit_mut_children_with(self);
+        }
+    }
+
+    fn visit_mut_prop_name(&mut self, name: &mut PropName) {
+        if let PropName::Computed(..) = name {
+            name.visit_mut_children_with(self);
+        }
+    }
+
+    fn visit_mut_labeled_stmt(&mut self, labeled: &mut LabeledStmt) {
+        labeled.body.visit_mut_with(self);
+    }
+
+    fn visit_mut_break_stmt(&mut self, _: &mut BreakStmt) {}
+
+    fn visit_mut_continue_stmt(&mut self, _: &mut ContinueStmt) {}
+
+    fn visit_mut_import_named_specifier(&mut self, specifier: &mut ImportNamedSpecifier) {
+        specifier.local.visit_mut_with(self);
+    }
+
+    fn visit_mut_export_named_specifier(&mut self, specifier: &mut ExportNamedSpecifier) {
+        specifier.orig.visit_mut_with(self);
     }
 
     // All code below this line is for deleting marked nodes.
@@ -169,6 +877,24 @@ impl VisitMut for Visitor {
         }
     }
 
+    // Remove empty VariableDeclaration nodes in `for` loops
+    fn visit_mut_for_stmt(&mut self, for_stmt: &mut ForStmt) {
+        for_stmt.visit_mut_children_with(self);
+
+        if let Some(VarDeclOrExpr::VarDecl(var)) = &for_stmt.init {
+            if var.decls.is_empty() {
+                for_stmt.init = None;
+            }
+        }
+    }
+
+    // Remove empty statements.
+    fn visit_mut_stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        stmts.visit_mut_children_with(self);
+
+        stmts.retain(|stmt| !matches!(stmt, Stmt::Empty(..)));
+    }
+
     // Remove top-level statements.
     fn visit_mut_module_items(&mut self, stmts: &mut Vec<ModuleItem>) {
         stmts.visit_mut_children_with(self);
@@ -177,51 +903,209 @@ impl VisitMut for Visitor {
     }
 }
 
-#[derive(Default)]
-struct FunctionVisitor {
-    /// Function [Id]'s.
-    functions: HashSet<Id>,
+#[cfg(test)]
+mod tests {
+    use crate::deobfuscate::{Deobfuscator, Pass};
+
+    /// Runs the pass with and without the resolver, which must give the same output.
+    fn deobfuscate(code: &str) -> String {
+        let run = |passes: &[Pass]| Deobfuscator::new()
+            .passes(passes)
+            .deobfuscate(code.to_string())
+            .expect("deobfuscate failed")
+            .code;
+
+        let output = run(&[Pass::ProxyVars]);
+        assert_eq!(output, run(&[Pass::Resolver, Pass::ProxyVars]));
+        output
+    }
 
-    /// Identifier names and their highest (deepest) scopes.
-    /// Used for collision checking.
-    identifiers: HashMap<JsWord, SyntaxContext>,
+    #[test]
+    fn test_shadowed_by_param() {
+        let code = r#"var r = c;
+function c() {}
+function doStuff(c) {
+    return r() + c;
 }
+c();
+"#;
+        assert_eq!(deobfuscate(code), r#"function proxyFn0() {}
+function doStuff(c) {
+    return proxyFn0() + c;
+}
+proxyFn0();
+"#);
+    }
 
-impl VisitMut for FunctionVisitor {
-    // Store FunctionDeclaration's identifiers so we can check in
-    // visit_mut_var_declarator if an Id is a function
-    fn visit_mut_fn_decl(&mut self, fn_decl: &mut FnDecl) {
-        self.functions.insert(fn_decl.ident.to_id());
-        fn_decl.visit_mut_children_with(self);
+    #[test]
+    fn test_shadowed_in_nested_function() {
+        let code = r#"function c() {}
+function outer() {
+    var r = c;
+    function inner() {
+        var c = 1;
+        return r(c);
+    }
+    return c(inner, { c });
+}
+"#;
+        assert_eq!(deobfuscate(code), r#"function proxyFn0() {}
+function outer() {
+    function inner() {
+        var c = 1;
+        return proxyFn0(c);
+    }
+    return proxyFn0(inner, {
+        c: proxyFn0
+    });
+}
+"#);
     }
 
-    // Store the highest (deepest) scope index for each identifier.
-    fn visit_mut_ident(&mut self, ident: &mut Ident) {
-        if let Some(v) = self.identifiers.get(&ident.sym) {
-            if ident.span.ctxt > *v {
-                self.identifiers.insert(ident.sym.clone(), ident.span.ctxt);
-            }
-        } else {
-            self.identifiers.insert(ident.sym.clone(), ident.span.ctxt);
-        }
+    #[test]
+    fn test_hoisted_function() {
+        let code = r#"c();
+var r = c, t = c;
+function f(c) {
+    return r(c) + t(c);
+}
+function c() {
+    return c;
+}
+"#;
+        assert_eq!(deobfuscate(code), r#"proxyFn0();
+function f(c) {
+    return proxyFn0(c) + proxyFn0(c);
+}
+function proxyFn0() {
+    return proxyFn0;
+}
+"#);
+    }
+
+    #[test]
+    fn test_block_scopes() {
+        let code = r#"function c() {}
+var r = c;
+while (r) {
+    let c = 1;
+    r(c);
+}
+function f() {
+    {
+        function g() {}
+        var t = g;
+    }
+    return t();
+}
+"#;
+        assert_eq!(deobfuscate(code), r#"function proxyFn0() {}
+while(proxyFn0){
+    let c = 1;
+    proxyFn0(c);
+}
+function f() {
+    {
+        function g() {}
+        var t = g;
     }
+    return t();
 }
+"#);
+    }
 
-/// Renames functions.
-struct RenameFunctionVisitor {
-    replacements: HashMap<Id, JsWord>
+    #[test]
+    fn test_alias_chain() {
+        let code = r#"function c() {}
+var r = c;
+const t = r;
+for (let u = t;;) {
+    u(r, { t });
+}
+"#;
+        assert_eq!(deobfuscate(code), r#"function c() {}
+for(;;){
+    c(c, {
+        t: c
+    });
 }
+"#);
+    }
 
-impl VisitMut for RenameFunctionVisitor {
-    fn visit_mut_fn_decl(&mut self, fn_decl: &mut FnDecl) {
-        // Remove id from replacements and set the function name
-        if let Some(new_name) = self.replacements.remove(&fn_decl.ident.to_id()) {
-            fn_decl.ident.sym = new_name;
+    #[test]
+    fn test_function_expression() {
+        let code = r#"var f = function(a) {
+    return a;
+}, g = (a)=>a;
+var r = f, t = g;
+r(t(1));
+"#;
+        assert_eq!(deobfuscate(code), r#"var f = function(a) {
+    return a;
+}, g = (a)=>a;
+f(g(1));
+"#);
+    }
 
-            // Visit children if we have remaining functions to replace
-            if !self.replacements.is_empty() {
-                fn_decl.visit_mut_children_with(self);
-            }
-        }
+    #[test]
+    fn test_string_array_getter() {
+        let code = r#"function _0x1b2c(e, s) {
+    var t = _0x4d5e();
+    return _0x1b2c = function(e, s) {
+        return t[e - 0x100];
+    }, _0x1b2c(e, s);
+}
+function f() {
+    var r = _0x1b2c, n = r;
+    return n(0x100) + r(0x101);
+}
+"#;
+        assert_eq!(deobfuscate(code), r#"function _0x1b2c(e, s) {
+    var t = _0x4d5e();
+    return _0x1b2c = function(e, s) {
+        return t[e - 0x100];
+    }, _0x1b2c(e, s);
+}
+function f() {
+    return _0x1b2c(0x100) + _0x1b2c(0x101);
+}
+"#);
+    }
+
+    #[test]
+    fn test_self_reassigning_function() {
+        // Only the string array functions return the same thing after replacing themselves
+        let code = r#"function c() {
+    c = g;
+}
+function g() {}
+var r = c;
+c();
+r();
+"#;
+        assert_eq!(deobfuscate(code), code);
+    }
+
+    #[test]
+    fn test_unsafe_aliases() {
+        let code = r#"function c() {}
+var f = function() {};
+f = null;
+var r = c;
+r = null;
+var t = f;
+function g(u) {
+    var u = c;
+    return arguments[0] + u();
+}
+function h() {
+    c = null;
+}
+var n = c;
+var m = g;
+for(m in h){}
+r(t(n(m)));
+"#;
+        assert_eq!(deobfuscate(code), code);
     }
 }
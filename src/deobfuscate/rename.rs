@@ -0,0 +1,433 @@
+use std::collections::{HashMap, HashSet};
+use swc_core::common::Span;
+use swc_core::ecma::ast::{ArrowExpr, AssignPat, BindingIdent, CatchClause, ClassDecl, Expr, ExprStmt, FnDecl, FnExpr, Function, Id, Ident, KeyValuePatProp, KeyValueProp, MemberProp, ObjectPatProp, Pat, Program, Prop, PropName, Stmt, VarDeclarator};
+use swc_core::ecma::atoms::JsWord;
+use swc_core::ecma::visit::{VisitMut, VisitMutWith};
+
+/// Renames mangled identifiers, like `_0x3f2a1b` or `e`, based on their role:
+/// `input` for the challenge's parameter, `argN` for other parameters,
+/// `fnN` for functions and `varN` for other variables.
+///
+/// Names of one or two characters, like `e`, are only treated as mangled in the
+/// challenge, a function expression taking the input, where every name is minified.
+/// Elsewhere they're as likely to be hand-written, like `i` or `id`, so only names
+/// like `_0x3f2a1b` are renamed.
+///
+/// Bindings are told apart by their [Id], so the resolver must run first, which
+/// [Deobfuscator](super::Deobfuscator) makes sure of.
+/// New names are unique across the whole program, so they can't be shadowed
+/// and never collide with a name that is already used. Bindings declared
+/// outside of functions are global, so they're left alone as other scripts
+/// can use them.
+#[derive(Default)]
+pub struct Visitor {
+    /// The names that were changed, in the order they're declared.
+    pub renamed: Vec<Renamed>
+}
+
+/// A binding that [Visitor] renamed.
+#[derive(Debug, Clone)]
+pub struct Renamed {
+    /// The name in the obfuscated code.
+    pub old: JsWord,
+
+    /// The new name.
+    pub new: JsWord,
+
+    /// Where the binding is declared in the original code.
+    pub span: Span
+}
+
+/// What a binding is used for, which decides its new name.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Role {
+    Input,
+    Param,
+    Function,
+    Var
+}
+
+impl Role {
+    /// The prefix of names given to bindings with this role.
+    fn prefix(self) -> &'static str {
+        match self {
+            Role::Input => "input",
+            Role::Param => "arg",
+            Role::Function => "fn",
+            Role::Var => "var"
+        }
+    }
+}
+
+/// If `name` looks like it was generated by an obfuscator. Short names
+/// are only counted if `is_minified`.
+fn is_mangled(name: &str, is_minified: bool) -> bool {
+    if let Some(hex) = name.strip_prefix("_0x") {
+        return !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    is_minified && name.chars().count() <= 2
+}
+
+/// If `program` is the challenge: a single function expression that takes the input.
+fn is_challenge(program: &Program) -> bool {
+    let Program::Script(script) = program else {
+        return false;
+    };
+    let [Stmt::Expr(ExprStmt { expr, .. })] = script.body.as_slice() else {
+        return false;
+    };
+    let mut expr = &**expr;
+    while let Expr::Paren(paren) = expr {
+        expr = &paren.expr;
+    }
+    matches!(expr, Expr::Fn(fn_expr) if fn_expr.function.params.len() == 1)
+}
+
+impl VisitMut for Visitor {
+    fn visit_mut_program(&mut self, program: &mut Program) {
+        // Find the bindings and every name in use. Only the challenge has an input
+        let is_challenge = is_challenge(program);
+        let mut bindings_visitor = BindingsVisitor {
+            found_input: !is_challenge,
+            ..Default::default()
+        };
+        program.visit_mut_children_with(&mut bindings_visitor);
+
+        // Name the bindings in the order they're declared
+        let mut used_names = bindings_visitor.names;
+        let mut counters: HashMap<Role, usize> = HashMap::new();
+        let mut renames = HashMap::new();
+        for (id, role, span) in bindings_visitor.bindings {
+            if renames.contains_key(&id) || !is_mangled(&id.0, is_challenge) {
+                continue;
+            }
+
+            let new_name = loop {
+                let name = match role {
+                    Role::Input if !used_names.contains(&JsWord::from("input")) => String::from("input"),
+                    role => {
+                        let counter = counters.entry(role).or_default();
+                        *counter += 1;
+                        format!("{}{}", role.prefix(), *counter - 1)
+                    }
+                };
+                let name = JsWord::from(name);
+                if !used_names.contains(&name) {
+                    break name;
+                }
+            };
+            used_names.insert(new_name.clone());
+
+            self.renamed.push(Renamed {
+                old: id.0.clone(),
+                new: new_name.clone(),
+                span
+            });
+            renames.insert(id, new_name);
+        }
+
+        if !renames.is_empty() {
+            let mut renamer = RenameVisitor { renames };
+            program.visit_mut_children_with(&mut renamer);
+        }
+    }
+}
+
+/// Finds the bindings declared in the program and the names in use.
+#[derive(Default)]
+struct BindingsVisitor {
+    /// Each binding with its role and where it's declared, in the order they're declared.
+    /// A binding can appear more than once, for example `var` declarations of the same variable.
+    bindings: Vec<(Id, Role, Span)>,
+
+    /// Every identifier name in the program, including globals and properties.
+    names: HashSet<JsWord>,
+
+    /// If the challenge's parameter was found, or there isn't one.
+    /// This is only used internally.
+    found_input: bool,
+
+    /// If the visitor is inside a function.
+    /// This is only used internally.
+    is_inside_fn: bool
+}
+
+impl BindingsVisitor {
+    /// Adds `params`. The first parameter of the first function is the challenge's input,
+    /// unless [BindingsVisitor::found_input] is already set.
+    fn add_params<'a>(&mut self, params: impl Iterator<Item = &'a Pat>) {
+        for param in params {
+            if let Pat::Ident(id) = param {
+                let role = if self.found_input {
+                    Role::Param
+                } else {
+                    self.found_input = true;
+                    Role::Input
+                };
+                self.bindings.push((id.to_id(), role, id.id.span));
+            }
+        }
+        // Only the first function can have the input
+        self.found_input = true;
+    }
+
+    /// Adds the parameters of `function`.
+    fn add_function(&mut self, function: &Function) {
+        self.add_params(function.params.iter().map(|param| &param.pat));
+    }
+
+    /// Adds a declared binding, unless it's declared outside of functions.
+    fn add_declaration(&mut self, id: Id, role: Role, span: Span) {
+        if self.is_inside_fn {
+            self.bindings.push((id, role, span));
+        }
+    }
+}
+
+impl VisitMut for BindingsVisitor {
+    fn visit_mut_fn_decl(&mut self, fn_decl: &mut FnDecl) {
+        self.add_declaration(fn_decl.ident.to_id(), Role::Function, fn_decl.ident.span);
+        self.add_function(&fn_decl.function);
+        fn_decl.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_function(&mut self, function: &mut Function) {
+        let old_is_inside_fn = self.is_inside_fn;
+        self.is_inside_fn = true;
+        function.visit_mut_children_with(self);
+        self.is_inside_fn = old_is_inside_fn;
+    }
+
+    fn visit_mut_fn_expr(&mut self, fn_expr: &mut FnExpr) {
+        if let Some(ident) = &fn_expr.ident {
+            self.bindings.push((ident.to_id(), Role::Function, ident.span));
+        }
+        self.add_function(&fn_expr.function);
+        fn_expr.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_arrow_expr(&mut self, arrow: &mut ArrowExpr) {
+        self.add_params(arrow.params.iter());
+
+        let old_is_inside_fn = self.is_inside_fn;
+        self.is_inside_fn = true;
+        arrow.visit_mut_children_with(self);
+        self.is_inside_fn = old_is_inside_fn;
+    }
+
+    fn visit_mut_class_decl(&mut self, class_decl: &mut ClassDecl) {
+        self.add_declaration(class_decl.ident.to_id(), Role::Var, class_decl.ident.span);
+        class_decl.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_catch_clause(&mut self, catch: &mut CatchClause) {
+        if let Some(Pat::Ident(id)) = &catch.param {
+            self.bindings.push((id.to_id(), Role::Var, id.id.span));
+        }
+        catch.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        if let Pat::Ident(id) = &declarator.name {
+            let role = match declarator.init.as_deref() {
+                Some(Expr::Fn(..) | Expr::Arrow(..)) => Role::Function,
+                _ => Role::Var
+            };
+            self.add_declaration(id.to_id(), role, id.id.span);
+        }
+        declarator.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_ident(&mut self, id: &mut Ident) {
+        self.names.insert(id.sym.clone());
+    }
+}
+
+/// Renames bindings and their references.
+struct RenameVisitor {
+    /// The new name of each binding.
+    renames: HashMap<Id, JsWord>
+}
+
+impl VisitMut for RenameVisitor {
+    fn visit_mut_ident(&mut self, id: &mut Ident) {
+        if let Some(new_name) = self.renames.get(&id.to_id()) {
+            id.sym = new_name.clone();
+        }
+    }
+
+    // Property names aren't bindings
+    fn visit_mut_member_prop(&mut self, prop: &mut MemberProp) {
+        if let MemberProp::Computed(..) = prop {
+            prop.visit_mut_children_with(self);
+        }
+    }
+
+    fn visit_mut_prop_name(&mut self, name: &mut PropName) {
+        if let PropName::Computed(..) = name {
+            name.visit_mut_children_with(self);
+        }
+    }
+
+    fn visit_mut_prop(&mut self, prop: &mut Prop) {
+        // `{ a }` becomes `{ a: arg0 }` so the key stays the same
+        if let Prop::Shorthand(id) = prop {
+            if let Some(new_name) = self.renames.get(&id.to_id()) {
+                let mut value = id.clone();
+                value.sym = new_name.clone();
+                *prop = Prop::KeyValue(KeyValueProp {
+                    key: PropName::Ident(id.clone()),
+                    value: Box::new(Expr::Ident(value))
+                });
+                return;
+            }
+        }
+        prop.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_object_pat_prop(&mut self, prop: &mut ObjectPatProp) {
+        // `{ a = 1 } = o` becomes `{ a: arg0 = 1 } = o` so the key stays the same
+        if let ObjectPatProp::Assign(assign) = prop {
+            if let Some(new_name) = self.renames.get(&assign.key.to_id()) {
+                let mut value = assign.key.clone();
+                value.sym = new_name.clone();
+                let value = Pat::Ident(BindingIdent::from(value));
+                let value = match assign.value.take() {
+                    Some(mut right) => {
+                        right.visit_mut_with(self);
+                        Pat::Assign(AssignPat {
+                            span: assign.span,
+                            left: Box::new(value),
+                            right
+                        })
+                    },
+                    None => value
+                };
+                *prop = ObjectPatProp::KeyValue(KeyValuePatProp {
+                    key: PropName::Ident(assign.key.clone()),
+                    value: Box::new(value)
+                });
+                return;
+            }
+        }
+        prop.visit_mut_children_with(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::deobfuscate::{Deobfuscator, Pass};
+
+    #[test]
+    fn test_rename() {
+        let code = r#"(function(a) {
+            function _0x3f2a1b(e, s) {
+                var t = e + s, input = 1;
+                return { t, marker: t * input };
+            }
+            var n = function(e) { return e; };
+            return _0x3f2a1b(a, n(Math.PI));
+        })"#;
+        let output = Deobfuscator::new()
+            .passes(&[Pass::Resolver, Pass::Rename])
+            .deobfuscate(code.to_string())
+            .expect("deobfuscate failed");
+
+        assert_eq!(output.code, r#"(function(input0) {
+    function fn0(arg0, arg1) {
+        var var0 = arg0 + arg1, input = 1;
+        return {
+            t: var0,
+            marker: var0 * input
+        };
+    }
+    var fn1 = function(arg2) {
+        return arg2;
+    };
+    return fn0(input0, fn1(Math.PI));
+});
+"#);
+
+        let renamed: Vec<(&str, &str)> = output.renamed
+            .iter()
+            .map(|renamed| (&*renamed.old, &*renamed.new))
+            .collect();
+        assert_eq!(renamed, vec![
+            ("a", "input0"),
+            ("_0x3f2a1b", "fn0"),
+            ("e", "arg0"),
+            ("s", "arg1"),
+            ("t", "var0"),
+            ("n", "fn1"),
+            ("e", "arg2")
+        ]);
+    }
+
+    #[test]
+    fn test_resolver_added() {
+        // Without the resolver, both `e` parameters would be the same binding
+        let code = "(function(a) { function f(e) { return e; } function g(e) { return e; } return f(a) + g(a); })";
+        let output = Deobfuscator::new()
+            .passes(&[Pass::Rename])
+            .deobfuscate(code.to_string())
+            .expect("deobfuscate failed");
+
+        let renamed: Vec<(&str, &str)> = output.renamed
+            .iter()
+            .map(|renamed| (&*renamed.old, &*renamed.new))
+            .collect();
+        assert_eq!(renamed, vec![
+            ("a", "input"),
+            ("f", "fn0"),
+            ("e", "arg0"),
+            ("g", "fn1"),
+            ("e", "arg1")
+        ]);
+    }
+
+    #[test]
+    fn test_keep_properties_and_globals() {
+        // Shorthand patterns read the property with the binding's name,
+        // and script level bindings are global
+        let code = r#"var _0x1 = 1;
+function _0x2(_0x3) {
+    var _0x4, _0x5;
+    ({ _0x4, _0x5 = _0x3 } = _0x3);
+    return [_0x4, _0x5, _0x1];
+}"#;
+        let output = Deobfuscator::new()
+            .passes(&[Pass::Resolver, Pass::Rename])
+            .deobfuscate(code.to_string())
+            .expect("deobfuscate failed");
+
+        assert_eq!(output.code, r#"var _0x1 = 1;
+function _0x2(arg0) {
+    var var0, var1;
+    ({ _0x4: var0, _0x5: var1 = arg0 } = arg0);
+    return [
+        var0,
+        var1,
+        _0x1
+    ];
+}
+"#);
+    }
+
+    #[test]
+    fn test_keep_short_names() {
+        // Short names are only mangled in the challenge
+        let code = r#"function f(s) {
+    for(var i = 0, id = s; i < id; i++){
+        g(i);
+    }
+}
+"#;
+        let output = Deobfuscator::new()
+            .passes(&[Pass::Resolver, Pass::Rename])
+            .deobfuscate(code.to_string())
+            .expect("deobfuscate failed");
+
+        assert_eq!(output.code, code);
+        assert!(output.renamed.is_empty());
+    }
+}
@@ -0,0 +1,60 @@
+use swc_core::ecma::ast::{BinaryOp, Expr, Lit, Str};
+use swc_core::ecma::atoms::JsWord;
+use swc_core::ecma::visit::{VisitMut, VisitMutWith};
+
+/// Joins concatenations of string literals, like the ones made by
+/// obfuscator.io's `splitStrings` option.
+///
+/// Example:
+/// ```js
+/// globalThis["mar" + "ker"];
+/// ```
+///
+/// is replaced with:
+///
+/// ```js
+/// globalThis["marker"];
+/// ```
+pub struct Visitor;
+
+impl VisitMut for Visitor {
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        expr.visit_mut_children_with(self);
+
+        match expr {
+            // Remove parentheses around strings so they can be joined
+            Expr::Paren(paren) => {
+                if let Expr::Lit(Lit::Str(..)) = &*paren.expr {
+                    *expr = *paren.expr.clone();
+                }
+            },
+            Expr::Bin(bin) if bin.op == BinaryOp::Add => {
+                if let (Expr::Lit(Lit::Str(left)), Expr::Lit(Lit::Str(right))) = (&*bin.left, &*bin.right) {
+                    *expr = Expr::Lit(Lit::Str(Str {
+                        span: bin.span,
+                        value: JsWord::from(format!("{}{}", left.value, right.value)),
+                        raw: None
+                    }));
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::deobfuscate::{Deobfuscator, Pass};
+
+    #[test]
+    fn test_join_strings() {
+        let code = r#"globalThis["mar" + ("k" + "e") + "r"]; a + "b" + "c";"#;
+        let output = Deobfuscator::new()
+            .passes(&[Pass::StringConcat, Pass::ComputedMemberExpr])
+            .deobfuscate(code.to_string())
+            .expect("deobfuscate failed");
+
+        // Only string-only concatenations are joined
+        assert_eq!(output.code, "globalThis.marker;\na + \"b\" + \"c\";\n");
+    }
+}
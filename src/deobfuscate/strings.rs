@@ -1,199 +1,620 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::default::Default;
-use std::str::FromStr;
-use swc_core::common::{Mark, Span};
+use std::fmt::{Display, Formatter};
+use swc_core::common::Span;
 use swc_core::common::errors::HANDLER;
 use swc_core::common::util::take::Take;
-use swc_core::ecma::visit::{VisitMut, VisitMutWith};
-use swc_core::ecma::ast::{ArrayLit, AssignExpr, BinaryOp, BinExpr, Callee, CallExpr, Decl, Expr, ExprStmt, FnDecl, Id, Ident, Lit, ModuleItem, Number, op, Program, Stmt, Str, VarDeclarator};
+use swc_core::ecma::visit::{Visit, VisitMut, VisitMutWith, VisitWith};
+use swc_core::ecma::ast::{ArrowExpr, AssignExpr, BinaryOp, BinExpr, BlockStmtOrExpr, Callee, CallExpr, Decl, Expr, ExprStmt, FnDecl, Function, Id, Ident, Lit, MemberProp, ModuleItem, op, Pat, Program, ReturnStmt, Stmt, Str, UnaryOp, VarDeclarator};
 use swc_core::ecma::atoms::JsWord;
-use swc_ecma_transforms::optimization::simplify::expr_simplifier;
 
 /// Replaces obfuscated strings with the real strings.
-pub struct Visitor;
+#[derive(Default)]
+pub struct Visitor {
+    /// The calls to the index function, or one of its wrappers, that couldn't be
+    /// resolved to a string. These are left in the code as they are.
+    pub unresolved_calls: Vec<UnresolvedCall>,
+
+    /// The string arrays that were decoded.
+    pub string_arrays: Vec<StringArray>
+}
+
+/// A string array that [Visitor] decoded.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct StringArray {
+    /// The name of the function that returns the string array,
+    /// or of the array if the index function references it directly.
+    pub array_fn: String,
+
+    /// The name of the function that indexes the string array.
+    pub index_fn: String,
+
+    /// The offset the index function applies to indexes, if it
+    /// applies a single operator and a constant.
+    pub offset: Option<f64>,
+
+    /// The operator the offset is applied with, like `-`.
+    pub operator: Option<String>,
+
+    /// How many times the array was rotated until the checksum matched.
+    pub rotations: usize,
+
+    /// The strings after rotating. RC4 encoded strings are left encoded,
+    /// since each call has its own key.
+    pub strings: Vec<String>
+}
+
+/// A call to the index function, or one of its wrappers, that couldn't be resolved
+/// to a string, for example because an argument isn't a constant.
+#[derive(Debug, Clone)]
+pub struct UnresolvedCall {
+    /// Where the call is in the original code.
+    /// Use [crate::deobfuscate::Deobfuscator::source_map] to look up its line.
+    pub span: Span,
+
+    /// The name of the function called.
+    pub callee: JsWord,
+
+    /// Why the index didn't map to a string, if the index is a constant.
+    pub index_error: Option<IndexError>
+}
 
 impl VisitMut for Visitor {
     fn visit_mut_program(&mut self, program: &mut Program) {
-        /// Matches `$e` to `Some`. If `None`, `$err` is emitted and the function returns.
-        macro_rules! try_unwrap {
-            ($e:expr, $err:expr) => {
-                match $e {
-                    Some(v) => v,
-                    None => {
-                        HANDLER.with(|handler| {
-                            handler.err($err);
-                        });
-                        return;
-                    }
-                }
-            };
-        }
-
-        // Find function that returns the obfuscated strings, along with the
-        // initial obfuscated strings
+        // Find the functions that return obfuscated strings, along with the
+        // initial obfuscated strings.
+        // If there aren't any then there are no obfuscated strings to replace,
+        // for example when this pass is run again on already deobfuscated code.
         let mut obf_strings = FindObfuscatedStringsVisitor::default();
         program.visit_mut_children_with(&mut obf_strings);
-        // The function Id that returns the obfuscated strings
-        let get_obf_strings_fn_id = try_unwrap!(
-            obf_strings.fn_id,
-            "Couldn't find obfuscated strings function"
-        );
-        // The obfuscated strings
-        let mut obfuscated_strings = try_unwrap!(
-            obf_strings.obfuscated_strings,
-            "Couldn't find obfuscated strings"
-        );
 
-        // Find the function that indexes the obfuscated strings array
-        let mut index_fn_visitor = FindIndexFunctionVisitor::new(
-            get_obf_strings_fn_id.clone()
-        );
-        program.visit_mut_children_with(&mut index_fn_visitor);
-        // Offset and operand
-        let index_data = try_unwrap!(index_fn_visitor.index, "Index data not found");
-        // Index function id
-        let index_fn_id = try_unwrap!(index_fn_visitor.fn_id, "Index function not found");
-
-        // Find the expression that is used to calculate the answer
-        // used for array modification
-        let mut expr_visitor = FindObfExpression::new(
-            get_obf_strings_fn_id.clone()
-        );
-        program.visit_mut_children_with(&mut expr_visitor);
-        // The original expression
-        let original_expr = try_unwrap!(
-            expr_visitor.expr,
-            "Array compute function not found"
-        );
-        // The answer to compare against
-        let answer = try_unwrap!(expr_visitor.answer, "Answer not found");
-        // The original obfuscated strings. We use this for failure checking
-        // so we don't loop infinitely if we some how don't compute the
-        // strings correctly.
-        let original_obfuscated_strings = obfuscated_strings.clone();
-        // Modify obfuscated_strings until we get the correct answer
-        loop {
-            // Convert parseInt calls to literal values
-            let mut expr = original_expr.clone();
-            let mut expr_evaluator = ExprVisitor::new(
-                index_data.clone(),
-                &obfuscated_strings
-            );
-            expr.visit_mut_children_with(&mut expr_evaluator);
-            // Evaluate the expression using expr_simplifier transform
-            let mut stmt = Stmt::Expr(ExprStmt {
-                span: Default::default(),
-                expr: Box::new(Expr::Bin(expr)),
-            });
-            let mut simplifier = expr_simplifier(
-                Mark::new(),
-                Default::default()
-            );
-            stmt.visit_mut_with(&mut simplifier);
-            // Try get literal value
-            if let Stmt::Expr(expr) = &stmt {
-                if let Expr::Lit(lit) = &*expr.expr {
-                    if let Lit::Num(n) = &lit {
-                        if n.value == answer {
-                            // Answer matches, stop looping
-                            break;
-                        }
-                    }
+        // Each string array has its own index function and rotation, so they're
+        // decoded independently. Nothing is removed until every array is decoded,
+        // so code that is still used isn't removed for arrays that can't be
+        // decoded.
+        let decoded: Vec<DecodedStringArray> = obf_strings.string_arrays()
+            .into_iter()
+            .chain(obf_strings.direct_arrays())
+            .filter_map(|(source_id, obfuscated_strings)| decode_string_array(program, source_id, obfuscated_strings))
+            .collect();
+        if decoded.is_empty() {
+            return;
+        }
+
+        // The functions that return the decoded arrays, and the arrays, which are
+        // either held by those functions or indexed directly
+        let getters: HashSet<Id> = obf_strings.getters
+            .iter()
+            .filter(|(fn_id, _)| decoded.iter().any(|array| array.source_id == *fn_id))
+            .map(|(fn_id, _)| fn_id.clone())
+            .collect();
+        let arrays: HashSet<Id> = decoded
+            .iter()
+            .map(|array| match obf_strings.getters.iter().find(|(fn_id, _)| *fn_id == array.source_id) {
+                Some((_, array_id)) => array_id.clone(),
+                None => array.source_id.clone()
+            })
+            .collect();
+
+        for array in decoded {
+            self.replace_strings(program, array);
+        }
+
+        // Remove the functions, and the arrays if nothing else references them.
+        // The index functions and rotations referencing them have been removed
+        let mut references_visitor = StringArrayReferencesVisitor {
+            getters: &getters,
+            arrays,
+            referenced: HashSet::new()
+        };
+        program.visit_mut_children_with(&mut references_visitor);
+        let mut remove_visitor = RemoveStringArraysVisitor {
+            functions: &getters,
+            unused_arrays: references_visitor.unused_arrays()
+        };
+        program.visit_mut_children_with(&mut remove_visitor);
+    }
+}
+
+/// A string array that was found and decoded, and can be replaced.
+struct DecodedStringArray {
+    /// The function that returns the obfuscated strings,
+    /// or the array if the index function references it directly.
+    source_id: Id,
+
+    /// The [Id] of the index function.
+    index_fn_id: Id,
+
+    /// How the index function maps indexes.
+    index_data: Index,
+
+    /// How the strings are encoded. Base64 strings are already decoded.
+    encoding: Encoding,
+
+    /// The wrappers of the index function.
+    wrappers: HashMap<Id, Wrapper>,
+
+    /// How many times the strings were rotated.
+    rotations: usize,
+
+    /// The strings after rotating.
+    strings: VecDeque<JsWord>
+}
+
+impl DecodedStringArray {
+    /// The [Decoder] that resolves calls to this array's index function.
+    fn decoder(&self) -> Decoder<'_> {
+        Decoder {
+            index_fn_id: self.index_fn_id.clone(),
+            index_data: self.index_data.clone(),
+            encoding: &self.encoding,
+            wrappers: &self.wrappers
+        }
+    }
+}
+
+/// Finds how the strings returned by `get_obf_strings_fn_id`, or held by it if
+/// the index function references the array directly, are indexed and rotated,
+/// where `obfuscated_strings` are the strings before they're rotated.
+/// The program isn't changed.
+///
+/// Returns `None` and emits an error if the strings can't be decoded.
+fn decode_string_array(
+    program: &Program,
+    get_obf_strings_fn_id: Id,
+    obfuscated_strings: Result<VecDeque<JsWord>, String>
+) -> Option<DecodedStringArray> {
+    /// Matches `$e` to `Some`. If `None`, `$err` is emitted and the function returns.
+    macro_rules! try_unwrap {
+        ($e:expr, $err:expr) => {
+            match $e {
+                Some(v) => v,
+                None => {
+                    HANDLER.with(|handler| {
+                        handler.err($err);
+                    });
+                    return None;
                 }
             }
-            // Got NaN, or the wrong answer. Continue modifying the deque.
-            let first = try_unwrap!(
-                obfuscated_strings.pop_front(),
-                "Obfuscated strings are empty"
-            );
-            obfuscated_strings.push_back(first);
-            // If the deque becomes original_obfuscated_strings (what we started with)
-            // then this means we failed to find the correct answer.
-            // This shouldn't happen, but is here for safety purposes so we don't
-            // loop forever.
-            // In this case, we emit an error and return.
-            if obfuscated_strings == original_obfuscated_strings {
-                HANDLER.with(|handler| {
-                    handler.err("Failed to compute obfuscated strings");
-                });
-                return;
+        };
+    }
+
+    /// Matches `$e` to `Ok`. If `Err`, the error is emitted and the function returns.
+    macro_rules! try_ok {
+        ($e:expr) => {
+            match $e {
+                Ok(v) => v,
+                Err(err) => {
+                    HANDLER.with(|handler| {
+                        handler.err(&err);
+                    });
+                    return None;
+                }
             }
+        };
+    }
+
+    // Find the function that indexes the obfuscated strings array.
+    // Without it and the rotation, the candidate isn't a string array
+    // and is left alone.
+    let mut index_fn_visitor = FindIndexFunctionVisitor::new(
+        get_obf_strings_fn_id.clone()
+    );
+    program.visit_children_with(&mut index_fn_visitor);
+    // Offset and operand, and the index function id
+    let (Some(index_data), Some(index_fn_id)) = (index_fn_visitor.index, index_fn_visitor.fn_id) else {
+        return None;
+    };
+
+    // Find the expression that is used to calculate the answer
+    // used for array modification
+    let mut expr_visitor = FindObfExpression::new(
+        get_obf_strings_fn_id.clone()
+    );
+    program.visit_children_with(&mut expr_visitor);
+    let rotation = expr_visitor.rotation?;
+
+    let mut obfuscated_strings = try_ok!(obfuscated_strings);
+
+    // Decode the strings if they're encoded, so the array modification
+    // below is computed with the decoded strings.
+    // RC4 encoded strings can't be decoded yet as each call has its own key.
+    let encoding = match index_fn_visitor.encoding {
+        Encoding::Base64(alphabet) => {
+            obfuscated_strings = try_unwrap!(
+                obfuscated_strings
+                    .iter()
+                    .map(|s| decode_base64(s, &alphabet).map(JsWord::from))
+                    .collect::<Option<VecDeque<JsWord>>>(),
+                "Failed to decode base64 strings"
+            );
+            Encoding::None
+        },
+        encoding => encoding
+    };
+
+    // Find the wrappers of the index function. Wrappers can call other
+    // wrappers, so keep looking until no new ones are found.
+    let mut wrappers_visitor = FindWrappersVisitor::new(&index_fn_id);
+    loop {
+        let count = wrappers_visitor.wrappers.len();
+        program.visit_children_with(&mut wrappers_visitor);
+        if wrappers_visitor.wrappers.len() == count {
+            break;
         }
+    }
+    let wrappers = wrappers_visitor.wrappers;
+    let decoder = Decoder {
+        index_fn_id: index_fn_id.clone(),
+        index_data: index_data.clone(),
+        encoding: &encoding,
+        wrappers: &wrappers
+    };
+
+    // The original expression and the answer to compare against
+    let (original_expr, answer) = try_ok!(rotation);
+    // Compile the expression so it can be evaluated for every rotation
+    // without touching the AST
+    let checksum = try_ok!(Checksum::compile(&original_expr, &decoder));
+    // Rotate the strings until we get the correct answer
+    let rotations = try_unwrap!(
+        checksum.solve(&obfuscated_strings, &encoding, answer),
+        "Failed to compute obfuscated strings"
+    );
+    obfuscated_strings.rotate_left(rotations);
+
+    Some(DecodedStringArray {
+        source_id: get_obf_strings_fn_id,
+        index_fn_id,
+        index_data,
+        encoding,
+        wrappers,
+        rotations,
+        strings: obfuscated_strings
+    })
+}
+
+impl Visitor {
+    /// Replaces the calls that index `array` with the plaintext strings, and
+    /// removes its index function, rotation and wrappers.
+    fn replace_strings(&mut self, program: &mut Program, array: DecodedStringArray) {
+        let decoder = array.decoder();
+
+        self.string_arrays.push(StringArray {
+            array_fn: array.source_id.0.to_string(),
+            index_fn: array.index_fn_id.0.to_string(),
+            offset: array.index_data.offset().map(|(_, offset)| offset),
+            operator: array.index_data.offset().map(|(op, _)| op.to_string()),
+            rotations: array.rotations,
+            strings: array.strings.iter().map(|s| s.to_string()).collect()
+        });
+
+        // Remove the index function and the rotation, which are
+        // replaced by the decoded strings
+        let mut remove_visitor = RemoveStringArraysVisitor {
+            functions: &HashSet::from([array.index_fn_id.clone()]),
+            unused_arrays: HashSet::new()
+        };
+        program.visit_mut_children_with(&mut remove_visitor);
+        let mut rotation_visitor = RemoveRotationVisitor {
+            get_obfuscated_strings_fn: &array.source_id,
+            removed: false
+        };
+        program.visit_mut_children_with(&mut rotation_visitor);
 
         // Remove call expressions and related code
-        let mut cleanup_visitor = CleanupVisitor::new(index_fn_id, index_data, &obfuscated_strings);
+        let mut cleanup_visitor = CleanupVisitor::new(&decoder, &array.strings);
         program.visit_mut_children_with(&mut cleanup_visitor);
+        self.unresolved_calls.append(&mut cleanup_visitor.unresolved_calls);
+
+        // Remove wrappers that are no longer used
+        if !array.wrappers.is_empty() {
+            let mut references_visitor = WrapperReferencesVisitor::new(&array.wrappers);
+            program.visit_mut_children_with(&mut references_visitor);
+            let mut remove_visitor = RemoveWrappersVisitor::new(
+                &array.wrappers,
+                references_visitor.used_wrappers()
+            );
+            program.visit_mut_children_with(&mut remove_visitor);
+        }
     }
 }
 
-/// Finds the function that returns the obfuscated strings
-/// along with the obfuscated strings.
+/// Finds the functions that return obfuscated strings along with the obfuscated strings.
+///
+/// These functions are recognised by their structure: they redefine themselves
+/// to return an array of strings.
+/// ```js
+/// function r() {
+///     var e = ["marker", "keys", "310898Wmonpm"];
+///     return r = function() {
+///         return e;
+///     }, r();
+/// }
+/// ```
+///
+/// The array can also be built by splitting a string, like `"marker|keys".split("|")`,
+/// and can be declared outside of the function. It can also be indexed directly
+/// without a function, see [FindObfuscatedStringsVisitor::direct_arrays].
 #[derive(Default)]
 struct FindObfuscatedStringsVisitor {
-    /// If we're currently inside a FunctionDeclaration.
-    /// This is only used internally.
-    is_inside_fn: bool,
+    /// The [Id] of each function that redefines itself to return a variable,
+    /// with the [Id] of the variable, in the order they're declared.
+    getters: Vec<(Id, Id)>,
 
-    /// The [Id] of the function that returns the obfuscated strings.
-    fn_id: Option<Id>,
+    /// The variables that are initialised to an array of strings, with the strings,
+    /// or why a string split into the array couldn't be split.
+    arrays: HashMap<Id, Result<VecDeque<JsWord>, String>>,
 
-    /// The obfuscated strings.
-    obfuscated_strings: Option<VecDeque<JsWord>>
+    /// The variables passed as the first argument to a function expression
+    /// that's called straight away, in the order they're passed.
+    rotated: Vec<Id>
+}
+
+impl FindObfuscatedStringsVisitor {
+    /// The [Id] of each function that returns obfuscated strings, with the
+    /// obfuscated strings, in the order they're declared.
+    fn string_arrays(&self) -> Vec<(Id, Result<VecDeque<JsWord>, String>)> {
+        self.getters
+            .iter()
+            .filter_map(|(fn_id, array_id)| Some((fn_id.clone(), self.arrays.get(array_id)?.clone())))
+            .collect()
+    }
+
+    /// The [Id] of each array of obfuscated strings that the index function references
+    /// directly instead of calling a function, with the obfuscated strings.
+    ///
+    /// These are recognised by being passed to the rotation function instead of a function:
+    /// ```js
+    /// var o = ["marker", "keys", "310898Wmonpm"];
+    /// (function(e, s) {
+    ///     ...
+    /// })(o, -981043 + -131413 * 5 + 2298101);
+    /// ```
+    fn direct_arrays(&self) -> Vec<(Id, Result<VecDeque<JsWord>, String>)> {
+        self.rotated
+            .iter()
+            .filter(|id| self.getters.iter().all(|(_, array_id)| array_id != *id))
+            .filter_map(|id| Some((id.clone(), self.arrays.get(id)?.clone())))
+            .collect()
+    }
+}
+
+/// Gets the strings in `expr` if it's a non-empty array of string literals,
+/// or a string literal split by a string literal.
+///
+/// Splitting returns an error when the separator is empty and the string has
+/// characters that are two UTF-16 code units, as they can't be split statically.
+fn get_string_array(expr: &Expr) -> Option<Result<VecDeque<JsWord>, String>> {
+    match expr {
+        Expr::Array(array) if !array.elems.is_empty() => array.elems
+            .iter()
+            .map(|element| match element {
+                // Spread operator shouldn't be present on any elements
+                Some(v) if v.spread.is_none() => match &*v.expr {
+                    Expr::Lit(Lit::Str(s)) => Some(s.value.clone()),
+                    _ => None
+                },
+                _ => None
+            })
+            .collect::<Option<VecDeque<JsWord>>>()
+            .map(Ok),
+        // "marker|keys".split("|")
+        Expr::Call(call) => {
+            let Expr::Member(member) = call.callee.as_expr()?.as_ref() else {
+                return None;
+            };
+            let is_split = match &member.prop {
+                MemberProp::Ident(id) => &*id.sym == "split",
+                MemberProp::Computed(computed) => matches!(&*computed.expr, Expr::Lit(Lit::Str(s)) if &*s.value == "split"),
+                MemberProp::PrivateName(..) => false
+            };
+            let (Expr::Lit(Lit::Str(string)), [separator]) = (&*member.obj, call.args.as_slice()) else {
+                return None;
+            };
+            if !is_split {
+                return None;
+            }
+            let separator = match &*separator.expr {
+                Expr::Lit(Lit::Str(s)) if separator.spread.is_none() => &s.value,
+                _ => return None
+            };
+
+            // An empty separator splits into UTF-16 code units, and half of
+            // a surrogate pair can't be represented
+            if separator.is_empty() {
+                return Some(
+                    string.value
+                        .chars()
+                        .map(|c| match c.len_utf16() {
+                            1 => Ok(JsWord::from(c.to_string())),
+                            _ => Err(format!("String array can't be split into UTF-16 code units at {:?}", c))
+                        })
+                        .collect()
+                );
+            }
+
+            Some(Ok(string.value.split(&**separator).map(JsWord::from).collect()))
+        },
+        _ => None
+    }
 }
 
 impl VisitMut for FindObfuscatedStringsVisitor {
     fn visit_mut_fn_decl(&mut self, fn_decl: &mut FnDecl) {
-        // Stop visiting if we already found the function
-        if self.fn_id.is_some() {
-            return;
+        fn_decl.visit_mut_children_with(self);
+
+        // The function redefines itself to return the array
+        let mut redefinition_visitor = RedefinitionVisitor {
+            fn_id: fn_decl.ident.to_id(),
+            returned: None
+        };
+        fn_decl.function.visit_children_with(&mut redefinition_visitor);
+        if let Some(array_id) = redefinition_visitor.returned {
+            self.getters.push((fn_decl.ident.to_id(), array_id));
         }
+    }
 
-        // Visit children of this function
-        let old_is_inside_fn = self.is_inside_fn;
-        self.is_inside_fn = true;
-        fn_decl.visit_mut_children_with(self);
-        self.is_inside_fn = old_is_inside_fn;
+    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        declarator.visit_mut_children_with(self);
 
-        // If the obfuscated strings were found then set fn_id
-        if self.obfuscated_strings.is_some() {
-            self.fn_id = Some(fn_decl.ident.to_id());
-            fn_decl.take();
+        if let (Pat::Ident(id), Some(init)) = (&declarator.name, &declarator.init) {
+            if let Some(strings) = get_string_array(init) {
+                self.arrays.insert(id.to_id(), strings);
+            }
         }
     }
 
-    fn visit_mut_array_lit(&mut self, array: &mut ArrayLit) {
-        if !self.is_inside_fn {
-            return;
+    fn visit_mut_call_expr(&mut self, call: &mut CallExpr) {
+        call.visit_mut_children_with(self);
+
+        let mut callee = match &call.callee {
+            Callee::Expr(expr) => expr,
+            _ => return
+        };
+        while let Expr::Paren(paren) = &**callee {
+            callee = &paren.expr;
         }
+        if let (Expr::Fn(..), Some(Expr::Ident(id))) = (&**callee, call.args.first().map(|arg| &*arg.expr)) {
+            if !self.rotated.contains(&id.to_id()) {
+                self.rotated.push(id.to_id());
+            }
+        }
+    }
+}
 
-        let mut obfuscated_strings = VecDeque::new();
-        for element in &array.elems {
-            if let Some(v) = element {
-                // Spread operator shouldn't be present on any elements
-                if v.spread.is_some() {
-                    return;
+/// Finds where a function redefines itself to return a variable,
+/// like `r = function() { return e; }`.
+struct RedefinitionVisitor {
+    /// The [Id] of the function.
+    fn_id: Id,
+
+    /// The [Id] of the variable the redefined function returns.
+    returned: Option<Id>
+}
+
+impl Visit for RedefinitionVisitor {
+    fn visit_assign_expr(&mut self, assignment: &AssignExpr) {
+        assignment.visit_children_with(self);
+
+        match assignment.left.as_ident() {
+            Some(id) if id.to_id() == self.fn_id => {},
+            _ => return
+        }
+        let returned = match &*assignment.right {
+            Expr::Fn(fn_expr) => match fn_expr.function.body.as_ref().map(|body| body.stmts.as_slice()) {
+                Some([Stmt::Return(ReturnStmt { arg: Some(arg), .. })]) => arg.as_ident(),
+                _ => None
+            },
+            Expr::Arrow(arrow) => match &*arrow.body {
+                BlockStmtOrExpr::Expr(expr) => expr.as_ident(),
+                BlockStmtOrExpr::BlockStmt(block) => match block.stmts.as_slice() {
+                    [Stmt::Return(ReturnStmt { arg: Some(arg), .. })] => arg.as_ident(),
+                    _ => None
                 }
+            },
+            _ => None
+        };
+        if let Some(returned) = returned {
+            self.returned = Some(returned.to_id());
+        }
+    }
+}
 
-                if let Expr::Lit(Lit::Str(s)) = &*v.expr {
-                    obfuscated_strings.push_back(s.value.clone());
-                } else {
-                    // All elements should be string literals
-                    return;
+/// Finds the string arrays that are referenced outside of the functions that return them.
+struct StringArrayReferencesVisitor<'a> {
+    /// The functions that return obfuscated strings.
+    getters: &'a HashSet<Id>,
+
+    /// The variables holding the obfuscated strings.
+    arrays: HashSet<Id>,
+
+    /// The arrays referenced outside of `getters`.
+    referenced: HashSet<Id>
+}
+
+impl<'a> StringArrayReferencesVisitor<'a> {
+    /// The arrays that aren't referenced and can be removed.
+    fn unused_arrays(self) -> HashSet<Id> {
+        self.arrays
+            .into_iter()
+            .filter(|id| !self.referenced.contains(id))
+            .collect()
+    }
+}
+
+impl<'a> VisitMut for StringArrayReferencesVisitor<'a> {
+    fn visit_mut_fn_decl(&mut self, fn_decl: &mut FnDecl) {
+        // References inside the functions don't count, as they're removed
+        if !self.getters.contains(&fn_decl.ident.to_id()) {
+            fn_decl.visit_mut_children_with(self);
+        }
+    }
+
+    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        // The declaration isn't a reference
+        match &declarator.name {
+            Pat::Ident(id) if self.arrays.contains(&id.to_id()) => declarator.init.visit_mut_with(self),
+            _ => declarator.visit_mut_children_with(self)
+        }
+    }
+
+    fn visit_mut_ident(&mut self, id: &mut Ident) {
+        if self.arrays.contains(&id.to_id()) {
+            self.referenced.insert(id.to_id());
+        }
+    }
+}
+
+/// Removes the functions that return or index obfuscated strings, and the string
+/// arrays declared outside of them that are no longer used.
+struct RemoveStringArraysVisitor<'a> {
+    /// The functions to remove.
+    functions: &'a HashSet<Id>,
+
+    /// The string arrays that aren't used.
+    unused_arrays: HashSet<Id>
+}
+
+impl<'a> VisitMut for RemoveStringArraysVisitor<'a> {
+    fn visit_mut_stmt(&mut self, s: &mut Stmt) {
+        s.visit_mut_children_with(self);
+
+        match s {
+            Stmt::Decl(Decl::Fn(fn_decl)) if self.functions.contains(&fn_decl.ident.to_id()) => {
+                s.take();
+            },
+            Stmt::Decl(Decl::Var(var)) => {
+                var.decls.retain(|declarator| match &declarator.name {
+                    Pat::Ident(id) => !self.unused_arrays.contains(&id.to_id()),
+                    _ => true
+                });
+                if var.decls.is_empty() {
+                    s.take();
                 }
-            } else {
-                // All elements should be Some
-                return;
-            }
+            },
+            _ => {}
         }
+    }
 
-        self.obfuscated_strings = Some(obfuscated_strings);
+    // Remove empty statements
+    fn visit_mut_stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        stmts.visit_mut_children_with(self);
+
+        stmts.retain(|s| !matches!(s, Stmt::Empty(..)));
+    }
+
+    // Remove empty ModuleItem's
+    fn visit_mut_module_items(&mut self, stmts: &mut Vec<ModuleItem>) {
+        stmts.visit_mut_children_with(self);
+        stmts.retain(|stmt| !matches!(stmt, ModuleItem::Stmt(Stmt::Empty(..))));
     }
 }
 
 /// Finds the function that indexes the obfuscated strings.
 struct FindIndexFunctionVisitor {
-    /// The [Id] of the function that returns the obfuscated strings,
-    /// obtained from [FindObfuscatedStringsVisitor].
+    /// The [Id] of the function that returns the obfuscated strings, or of the
+    /// array if it's referenced directly, obtained from [FindObfuscatedStringsVisitor].
     get_obfuscated_strings_fn: Id,
 
     /// If the visitor is currently inside a function declaration.
@@ -204,41 +625,662 @@ struct FindIndexFunctionVisitor {
     /// This is only used internally.
     is_inside_correct_fn: bool,
 
-    /// The index data, containing the offset and the binary operator.
+    /// The first parameter of the function the visitor is currently inside.
+    /// This is only used internally.
+    param: Option<Id>,
+
+    /// How the index function maps indexes.
     index: Option<Index>,
 
     /// The [Id] of the function that indexes strings.
-    fn_id: Option<Id>
+    fn_id: Option<Id>,
+
+    /// How the index function decodes the strings it indexes.
+    encoding: Encoding
 }
 
-#[derive(Copy, Clone)]
+/// How the index function maps the index it's called with to the real index,
+/// like `n = n - 0x84`.
+#[derive(Clone)]
 struct Index {
-    /// The operand to use to get the real index.
-    offset: f64,
-
-    /// The operator to use with offset.
-    op: BinaryOp
-}
-
-/// Computes a fake index into the real index.
-fn get_index(index: u32, offset: u32, op: BinaryOp) -> Option<u32> {
-    match op {
-        BinaryOp::LShift => Some(index << offset),
-        BinaryOp::RShift => Some(index >> offset),
-        BinaryOp::ZeroFillRShift => Some(index >> offset),
-        BinaryOp::Add => Some(index + offset),
-        BinaryOp::Sub => Some(index - offset),
-        BinaryOp::Mul => Some(index * offset),
-        BinaryOp::Div => Some(index / offset),
-        BinaryOp::Mod => Some(index % offset),
-        BinaryOp::BitOr => Some(index | offset),
-        BinaryOp::BitXor => Some(index ^ offset),
-        BinaryOp::BitAnd => Some(index & offset),
-        BinaryOp::Exp => Some(index.pow(offset)),
+    /// The index function's parameter that holds the index.
+    param: Id,
+
+    /// The expression the parameter is reassigned to, which only uses the
+    /// parameter, literals and arithmetic.
+    expr: Expr
+}
+
+impl Index {
+    /// Maps `index` to the real index, with JavaScript's number semantics.
+    fn map(&self, index: &Value) -> Result<usize, IndexError> {
+        let params = HashMap::from([(self.param.clone(), index.clone())]);
+        let real_index = match eval_arg(&self.expr, &params) {
+            Some(Value::Num(n)) => n,
+            _ => return Err(IndexError::NotANumber)
+        };
+
+        // Property keys are strings, so `-0` is the same as `0`
+        if real_index.fract() != 0.0 || real_index < 0.0 || real_index >= usize::MAX as f64 {
+            return Err(IndexError::Invalid(real_index));
+        }
+        Ok(real_index as usize)
+    }
+
+    /// The operator and constant the index is offset by, if the expression
+    /// is the parameter and a constant, like `n - 0x84`.
+    fn offset(&self) -> Option<(BinaryOp, f64)> {
+        let mut expr = &self.expr;
+        while let Expr::Paren(paren) = expr {
+            expr = &paren.expr;
+        }
+        let Expr::Bin(bin) = expr else {
+            return None;
+        };
+        if !matches!(&*bin.left, Expr::Ident(id) if id.to_id() == self.param) {
+            return None;
+        }
+        match eval_arg(&bin.right, &HashMap::new())? {
+            Value::Num(offset) => Some((bin.op, offset)),
+            _ => None
+        }
+    }
+}
+
+/// Why a call to the index function couldn't be mapped to a string.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IndexError {
+    /// The index function's transform didn't give a number for the index,
+    /// for example because a string was concatenated to it.
+    NotANumber,
+
+    /// The real index isn't a whole number that can index an array, like `-1` or `1.5`.
+    Invalid(f64),
+
+    /// The real index is past the end of the strings array.
+    OutOfRange {
+        /// The real index.
+        index: usize,
+
+        /// The length of the strings array.
+        len: usize
+    }
+}
+
+impl Display for IndexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IndexError::NotANumber => write!(f, "index isn't a number"),
+            IndexError::Invalid(index) => write!(f, "index {} isn't a valid array index", index),
+            IndexError::OutOfRange { index, len } => write!(f, "index {} is out of range for {} strings", index, len)
+        }
+    }
+}
+
+impl std::error::Error for IndexError {}
+
+/// How the strings in the obfuscated strings array are encoded.
+#[derive(Clone)]
+enum Encoding {
+    /// The strings are plaintext.
+    None,
+
+    /// The strings are base64 encoded with the given alphabet.
+    /// This is obfuscator.io's `stringArrayEncoding: ['base64']` option.
+    Base64(Vec<char>),
+
+    /// The strings are RC4 encrypted with a key passed to each call, then base64
+    /// encoded with the given alphabet.
+    /// This is obfuscator.io's `stringArrayEncoding: ['rc4']` option.
+    Rc4(Vec<char>)
+}
+
+/// Finds how the index function decodes strings.
+///
+/// obfuscator.io's base64 decoder is recognised by its alphabet, which is
+/// a string literal containing every base64 character once, and the
+/// `decodeURIComponent` call it finishes with. The RC4 decoder wraps the
+/// base64 decoder and is recognised by the `% 256` of its key scheduling.
+#[derive(Default)]
+struct FindEncodingVisitor {
+    /// The base64 alphabet.
+    alphabet: Option<Vec<char>>,
+
+    /// If `decodeURIComponent` is referenced.
+    has_decode_uri_component: bool,
+
+    /// If there's a `% 256` expression.
+    has_mod_256: bool
+}
+
+impl FindEncodingVisitor {
+    /// The encoding found by this visitor.
+    fn encoding(self) -> Encoding {
+        match self.alphabet {
+            Some(alphabet) if self.has_decode_uri_component && self.has_mod_256 => Encoding::Rc4(alphabet),
+            Some(alphabet) if self.has_decode_uri_component => Encoding::Base64(alphabet),
+            _ => Encoding::None
+        }
+    }
+}
+
+impl Visit for FindEncodingVisitor {
+    fn visit_bin_expr(&mut self, bin: &BinExpr) {
+        bin.visit_children_with(self);
+
+        if bin.op == op!("%") {
+            if let Expr::Lit(Lit::Num(n)) = &*bin.right {
+                if n.value == 256.0 {
+                    self.has_mod_256 = true;
+                }
+            }
+        }
+    }
+
+    fn visit_str(&mut self, s: &Str) {
+        let alphabet: Vec<char> = s.value.chars().collect();
+        // 64 characters, plus the optional padding character
+        if alphabet.len() != 64 && alphabet.len() != 65 {
+            return;
+        }
+
+        let mut seen = HashSet::new();
+        let is_alphabet = alphabet.iter().all(|c| {
+            (c.is_ascii_alphanumeric() || *c == '+' || *c == '/' || *c == '=') && seen.insert(*c)
+        });
+        if is_alphabet {
+            self.alphabet = Some(alphabet);
+        }
+    }
+
+    fn visit_ident(&mut self, id: &Ident) {
+        if &*id.sym == "decodeURIComponent" {
+            self.has_decode_uri_component = true;
+        }
+    }
+}
+
+/// Decodes a string encoded by obfuscator.io's base64 string encoding.
+///
+/// This is a port of the generated decoder: characters that aren't in `alphabet`
+/// are skipped, and the decoded bytes are UTF-8 decoded like `decodeURIComponent`.
+/// `None` is returned if the bytes aren't valid UTF-8, where `decodeURIComponent`
+/// would throw.
+fn decode_base64(input: &str, alphabet: &[char]) -> Option<String> {
+    let mut bytes = Vec::new();
+    // Characters decoded so far and the bits buffered from them
+    let mut count: i64 = 0;
+    let mut buffer: i64 = 0;
+
+    for c in input.trim_end_matches('=').chars() {
+        let value = match alphabet.iter().position(|a| *a == c) {
+            Some(v) => v as i64,
+            None => continue
+        };
+
+        buffer = if count % 4 != 0 { buffer * 64 + value } else { value };
+        count += 1;
+        // Every character after the first in a group of four completes a byte
+        if (count - 1) % 4 != 0 {
+            bytes.push(((buffer >> ((-2 * count) & 6)) & 0xff) as u8);
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+/// Decodes a string encoded by obfuscator.io's RC4 string encoding with the given key.
+///
+/// The input is base64 decoded with [decode_base64], then each UTF-16 code unit
+/// is decrypted like the generated decoder does.
+fn decode_rc4(input: &str, key: &str, alphabet: &[char]) -> Option<String> {
+    let input: Vec<u16> = decode_base64(input, alphabet)?.encode_utf16().collect();
+    let key: Vec<u16> = key.encode_utf16().collect();
+    if key.is_empty() {
+        return None;
+    }
+
+    // Key scheduling
+    let mut state: Vec<usize> = (0..256).collect();
+    let mut j = 0;
+    for i in 0..256 {
+        j = (j + state[i] + key[i % key.len()] as usize) % 256;
+        state.swap(i, j);
+    }
+
+    // Decrypt
+    let mut i = 0;
+    j = 0;
+    let output: Vec<u16> = input
+        .iter()
+        .map(|c| {
+            i = (i + 1) % 256;
+            j = (j + state[i]) % 256;
+            state.swap(i, j);
+            c ^ state[(state[i] + state[j]) % 256] as u16
+        })
+        .collect();
+
+    String::from_utf16(&output).ok()
+}
+
+/// Gets the plaintext string returned by a call to the index function with `args`,
+/// for example `x(305)`, or `x(305, 'kR#z')` if the strings are RC4 encoded.
+///
+/// The index is converted with `ToNumber`, so `x('0x131')` is the same as `x(305)`.
+/// Returns an [IndexError] if the index doesn't map to a string, or `Err(None)`
+/// if the string can't be decoded.
+fn get_string(
+    args: &[Value],
+    index_data: &Index,
+    encoding: &Encoding,
+    strings: &VecDeque<JsWord>
+) -> Result<JsWord, Option<IndexError>> {
+    let s = &strings[get_real_index(args, index_data, strings.len())?];
+
+    match encoding {
+        Encoding::Rc4(alphabet) => get_key(args)
+            .and_then(|key| decode_rc4(s, key, alphabet))
+            .map(JsWord::from)
+            .ok_or(None),
+        // Base64 strings are already decoded
+        Encoding::Base64(..) | Encoding::None => Ok(s.clone())
+    }
+}
+
+/// Gets the index into the strings array, which has `len` strings, that the
+/// index function is called with in `args`.
+fn get_real_index(args: &[Value], index_data: &Index, len: usize) -> Result<usize, IndexError> {
+    // Missing arguments are undefined
+    let index = index_data.map(args.first().unwrap_or(&Value::Undefined))?;
+    if index >= len {
+        return Err(IndexError::OutOfRange { index, len });
+    }
+    Ok(index)
+}
+
+/// Gets the RC4 key the index function is called with in `args`.
+fn get_key(args: &[Value]) -> Option<&JsWord> {
+    match args.get(1) {
+        Some(Value::Str(key)) => Some(key),
         _ => None
     }
 }
 
+/// A value passed to the index function or one of its wrappers.
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Num(f64),
+    Str(JsWord),
+    Undefined
+}
+
+impl Value {
+    /// Converts the value to a number, following JavaScript's `ToNumber`.
+    fn to_number(&self) -> f64 {
+        match self {
+            Value::Num(n) => *n,
+            Value::Str(s) => string_to_number(s),
+            Value::Undefined => f64::NAN
+        }
+    }
+}
+
+/// If `c` is whitespace or a line terminator in JavaScript.
+fn is_js_whitespace(c: char) -> bool {
+    // Rust considers U+0085 (next line) whitespace and U+FEFF (byte order mark) not
+    c == '\u{feff}' || (c.is_whitespace() && c != '\u{85}')
+}
+
+/// Converts a string to a number, following JavaScript's `ToNumber`.
+///
+/// Example: `'0x1a4'` is `420`, `' -12 '` is `-12`, `''` is `0` and `'12px'` is `NaN`.
+fn string_to_number(s: &str) -> f64 {
+    let s = s.trim_matches(is_js_whitespace);
+    if s.is_empty() {
+        return 0.0;
+    }
+
+    // Binary, octal and hex literals, which can't be signed
+    for (prefix, radix) in [("0x", 16), ("0X", 16), ("0o", 8), ("0O", 8), ("0b", 2), ("0B", 2)] {
+        if let Some(digits) = s.strip_prefix(prefix) {
+            if digits.is_empty() {
+                return f64::NAN;
+            }
+            return digits
+                .chars()
+                .try_fold(0.0, |n, c| c.to_digit(radix).map(|d| n * radix as f64 + d as f64))
+                .unwrap_or(f64::NAN);
+        }
+    }
+
+    match s {
+        "Infinity" | "+Infinity" => return f64::INFINITY,
+        "-Infinity" => return f64::NEG_INFINITY,
+        _ => {}
+    }
+
+    // Rust also accepts "inf" and "NaN", which JavaScript doesn't
+    if !s.chars().all(|c| matches!(c, '0'..='9' | '.' | 'e' | 'E' | '+' | '-')) {
+        return f64::NAN;
+    }
+    s.parse::<f64>().unwrap_or(f64::NAN)
+}
+
+/// Evaluates an argument passed to the index function or one of its wrappers.
+/// Identifiers are looked up in `params`, which holds the arguments of the
+/// wrapper being evaluated.
+///
+/// Returns `None` if the argument isn't a constant expression.
+fn eval_arg(expr: &Expr, params: &HashMap<Id, Value>) -> Option<Value> {
+    match expr {
+        Expr::Lit(Lit::Num(n)) => Some(Value::Num(n.value)),
+        Expr::Lit(Lit::Str(s)) => Some(Value::Str(s.value.clone())),
+        Expr::Ident(id) => params.get(&id.to_id()).cloned(),
+        Expr::Paren(paren) => eval_arg(&paren.expr, params),
+        Expr::Unary(unary) => {
+            let n = eval_arg(&unary.arg, params)?.to_number();
+            match unary.op {
+                UnaryOp::Minus => Some(Value::Num(-n)),
+                UnaryOp::Plus => Some(Value::Num(n)),
+                UnaryOp::Tilde => Some(Value::Num(!to_int32(n) as f64)),
+                _ => None
+            }
+        },
+        Expr::Bin(bin) => {
+            let left = eval_arg(&bin.left, params)?;
+            let right = eval_arg(&bin.right, params)?;
+            if bin.op == BinaryOp::Add {
+                match (&left, &right) {
+                    (Value::Str(l), Value::Str(r)) => {
+                        return Some(Value::Str(JsWord::from(format!("{}{}", l, r))));
+                    },
+                    // Concatenating a string with a number or undefined isn't supported
+                    (Value::Str(..), _) | (_, Value::Str(..)) => return None,
+                    _ => {}
+                }
+            }
+            let (l, r) = (left.to_number(), right.to_number());
+            match bin.op {
+                BinaryOp::Add => Some(Value::Num(l + r)),
+                BinaryOp::Sub => Some(Value::Num(l - r)),
+                BinaryOp::Mul => Some(Value::Num(l * r)),
+                BinaryOp::Div => Some(Value::Num(l / r)),
+                BinaryOp::Mod => Some(Value::Num(l % r)),
+                BinaryOp::Exp => Some(Value::Num(pow(l, r))),
+                BinaryOp::BitOr => Some(Value::Num((to_int32(l) | to_int32(r)) as f64)),
+                BinaryOp::BitXor => Some(Value::Num((to_int32(l) ^ to_int32(r)) as f64)),
+                BinaryOp::BitAnd => Some(Value::Num((to_int32(l) & to_int32(r)) as f64)),
+                // Shifts use the lowest 5 bits of the right side
+                BinaryOp::LShift => Some(Value::Num(to_int32(l).wrapping_shl(to_int32(r) as u32) as f64)),
+                BinaryOp::RShift => Some(Value::Num(to_int32(l).wrapping_shr(to_int32(r) as u32) as f64)),
+                BinaryOp::ZeroFillRShift => Some(Value::Num((to_int32(l) as u32).wrapping_shr(to_int32(r) as u32) as f64)),
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+/// Raises `base` to `exponent`, following JavaScript's `**`.
+fn pow(base: f64, exponent: f64) -> f64 {
+    // Rust gives 1 for these, but JavaScript gives NaN
+    if exponent.is_nan() || (base.abs() == 1.0 && exponent.is_infinite()) {
+        return f64::NAN;
+    }
+    base.powf(exponent)
+}
+
+/// A function that calls the index function, or another wrapper, with its
+/// arguments shifted or reordered. These are generated by obfuscator.io's
+/// `stringArrayWrappersCount` option.
+///
+/// Example:
+/// ```js
+/// function W(P, Q) {
+///     return x(Q - -0x3c, P);
+/// }
+/// ```
+#[derive(Clone)]
+struct Wrapper {
+    /// The wrapper's parameters.
+    params: Vec<Id>,
+
+    /// The arguments the wrapper passes to `target`.
+    args: Vec<Expr>,
+
+    /// The function the wrapper calls.
+    target: Id
+}
+
+/// Finds the wrappers of the index function, see [Wrapper].
+///
+/// A wrapper can call another wrapper, so this is run until no new
+/// wrappers are found.
+struct FindWrappersVisitor<'a> {
+    /// The [Id] of the index function.
+    index_fn_id: &'a Id,
+
+    /// The wrappers found so far.
+    wrappers: HashMap<Id, Wrapper>
+}
+
+impl<'a> FindWrappersVisitor<'a> {
+    fn new(index_fn_id: &'a Id) -> Self {
+        Self {
+            index_fn_id,
+            wrappers: HashMap::new()
+        }
+    }
+
+    /// Gets the [Wrapper] for a function with `params` that returns `expr`.
+    fn get_wrapper(&self, params: Vec<&Pat>, expr: &Expr) -> Option<Wrapper> {
+        let params = params
+            .into_iter()
+            .map(|pat| match pat {
+                Pat::Ident(id) => Some(id.to_id()),
+                _ => None
+            })
+            .collect::<Option<Vec<Id>>>()?;
+
+        let call = match expr {
+            Expr::Call(call) => call,
+            _ => return None
+        };
+        // Does the function call the index function or another wrapper?
+        let target = match &call.callee {
+            Callee::Expr(callee) => match &**callee {
+                Expr::Ident(id) => id.to_id(),
+                _ => return None
+            },
+            _ => return None
+        };
+        if target != *self.index_fn_id && !self.wrappers.contains_key(&target) {
+            return None;
+        }
+
+        let args = call.args
+            .iter()
+            .map(|arg| match arg.spread {
+                Some(..) => None,
+                None if is_wrapper_arg(&arg.expr, &params) => Some((*arg.expr).clone()),
+                None => None
+            })
+            .collect::<Option<Vec<Expr>>>()?;
+
+        Some(Wrapper {
+            params,
+            args,
+            target
+        })
+    }
+
+    /// Gets the [Wrapper] for `function` if its body is a single return statement.
+    fn get_fn_wrapper(&self, function: &Function) -> Option<Wrapper> {
+        let stmts = &function.body.as_ref()?.stmts;
+        match stmts.as_slice() {
+            [Stmt::Return(ReturnStmt { arg: Some(arg), .. })] => self.get_wrapper(
+                function.params.iter().map(|param| &param.pat).collect(),
+                arg
+            ),
+            _ => None
+        }
+    }
+
+    /// Gets the [Wrapper] for `arrow` if its body is an expression
+    /// or a single return statement.
+    fn get_arrow_wrapper(&self, arrow: &ArrowExpr) -> Option<Wrapper> {
+        let expr = match &*arrow.body {
+            BlockStmtOrExpr::Expr(expr) => &**expr,
+            BlockStmtOrExpr::BlockStmt(block) => match block.stmts.as_slice() {
+                [Stmt::Return(ReturnStmt { arg: Some(arg), .. })] => &**arg,
+                _ => return None
+            }
+        };
+        self.get_wrapper(arrow.params.iter().collect(), expr)
+    }
+}
+
+/// If `expr` uses `id`.
+fn uses_ident(expr: &Expr, id: &Id) -> bool {
+    match expr {
+        Expr::Ident(ident) => ident.to_id() == *id,
+        Expr::Paren(paren) => uses_ident(&paren.expr, id),
+        Expr::Unary(unary) => uses_ident(&unary.arg, id),
+        Expr::Bin(bin) => uses_ident(&bin.left, id) || uses_ident(&bin.right, id),
+        _ => false
+    }
+}
+
+/// If `expr` can be passed on by a wrapper, meaning it only uses literals,
+/// the wrapper's `params` and arithmetic.
+fn is_wrapper_arg(expr: &Expr, params: &[Id]) -> bool {
+    match expr {
+        Expr::Lit(Lit::Num(..)) | Expr::Lit(Lit::Str(..)) => true,
+        Expr::Ident(id) => params.contains(&id.to_id()),
+        Expr::Paren(paren) => is_wrapper_arg(&paren.expr, params),
+        Expr::Unary(unary) => is_wrapper_arg(&unary.arg, params),
+        Expr::Bin(bin) => is_wrapper_arg(&bin.left, params) && is_wrapper_arg(&bin.right, params),
+        _ => false
+    }
+}
+
+impl<'a> Visit for FindWrappersVisitor<'a> {
+    fn visit_fn_decl(&mut self, fn_decl: &FnDecl) {
+        fn_decl.visit_children_with(self);
+
+        let id = fn_decl.ident.to_id();
+        if self.wrappers.contains_key(&id) {
+            return;
+        }
+        if let Some(wrapper) = self.get_fn_wrapper(&fn_decl.function) {
+            self.wrappers.insert(id, wrapper);
+        }
+    }
+
+    fn visit_var_declarator(&mut self, declarator: &VarDeclarator) {
+        declarator.visit_children_with(self);
+
+        let id = match &declarator.name {
+            Pat::Ident(id) => id.to_id(),
+            _ => return
+        };
+        if self.wrappers.contains_key(&id) {
+            return;
+        }
+
+        let wrapper = match declarator.init.as_deref() {
+            Some(Expr::Fn(fn_expr)) => self.get_fn_wrapper(&fn_expr.function),
+            Some(Expr::Arrow(arrow)) => self.get_arrow_wrapper(arrow),
+            _ => None
+        };
+        if let Some(wrapper) = wrapper {
+            self.wrappers.insert(id, wrapper);
+        }
+    }
+}
+
+/// Resolves calls to the index function, or one of its wrappers, to plaintext strings.
+struct Decoder<'strings> {
+    /// The [Id] of the index function.
+    index_fn_id: Id,
+
+    /// The index data.
+    index_data: Index,
+
+    /// How the obfuscated strings are encoded.
+    encoding: &'strings Encoding,
+
+    /// The wrappers of the index function.
+    wrappers: &'strings HashMap<Id, Wrapper>
+}
+
+impl<'strings> Decoder<'strings> {
+    /// The maximum number of wrappers a call is resolved through.
+    /// This stops wrappers that call each other from looping forever.
+    const MAX_DEPTH: usize = 64;
+
+    /// If `id` is the index function or one of its wrappers.
+    fn is_decoder(&self, id: &Id) -> bool {
+        *id == self.index_fn_id || self.wrappers.contains_key(id)
+    }
+
+    /// Gets the plaintext string returned by `call`. If the callee isn't
+    /// a wrapper, it's treated as the index function.
+    ///
+    /// Returns an [IndexError] if the index doesn't map to a string, or `Err(None)`
+    /// if the call can't be resolved for another reason.
+    fn decode_call(&self, call: &CallExpr, strings: &VecDeque<JsWord>) -> Result<JsWord, Option<IndexError>> {
+        let args = self.index_args(call).ok_or(None)?;
+        get_string(&args, &self.index_data, self.encoding, strings)
+    }
+
+    /// Gets the arguments `call` ends up passing to the index function,
+    /// after going through any wrappers.
+    fn index_args(&self, call: &CallExpr) -> Option<Vec<Value>> {
+        let callee = match &call.callee {
+            Callee::Expr(callee) => match &**callee {
+                Expr::Ident(id) => id.to_id(),
+                _ => return None
+            },
+            _ => return None
+        };
+        let args = call.args
+            .iter()
+            .map(|arg| match arg.spread {
+                Some(..) => None,
+                None => eval_arg(&arg.expr, &HashMap::new())
+            })
+            .collect::<Option<Vec<Value>>>()?;
+
+        self.unwrap_args(&callee, args, 0)
+    }
+
+    /// Gets the arguments calling `fn_id` with `args` ends up passing to the index function.
+    fn unwrap_args(&self, fn_id: &Id, args: Vec<Value>, depth: usize) -> Option<Vec<Value>> {
+        let wrapper = match self.wrappers.get(fn_id) {
+            Some(v) => v,
+            None => return Some(args)
+        };
+        if depth >= Self::MAX_DEPTH {
+            return None;
+        }
+
+        // Missing arguments are undefined
+        let params = wrapper.params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| (param.clone(), args.get(i).cloned().unwrap_or(Value::Undefined)))
+            .collect();
+        let args = wrapper.args
+            .iter()
+            .map(|arg| eval_arg(arg, &params))
+            .collect::<Option<Vec<Value>>>()?;
+
+        self.unwrap_args(&wrapper.target, args, depth + 1)
+    }
+}
+
 impl FindIndexFunctionVisitor {
     /// Creates a new [FindIndexFunctionVisitor] with the obfuscated strings function identifier
     /// obtained from [FindObfuscatedStringsVisitor].
@@ -247,17 +1289,19 @@ impl FindIndexFunctionVisitor {
             get_obfuscated_strings_fn,
             is_inside_fn_decl: false,
             is_inside_correct_fn: false,
+            param: None,
             index: None,
-            fn_id: None
+            fn_id: None,
+            encoding: Encoding::None
         }
     }
 }
 
-impl VisitMut for FindIndexFunctionVisitor {
-    fn visit_mut_fn_decl(&mut self, fn_decl: &mut FnDecl) {
+impl Visit for FindIndexFunctionVisitor {
+    fn visit_fn_decl(&mut self, fn_decl: &FnDecl) {
         let old_is_inside_fn_decl = self.is_inside_fn_decl;
         self.is_inside_fn_decl = true;
-        fn_decl.visit_mut_children_with(self);
+        fn_decl.visit_children_with(self);
         self.is_inside_fn_decl = old_is_inside_fn_decl;
 
         // Set fn_id.
@@ -266,7 +1310,18 @@ impl VisitMut for FindIndexFunctionVisitor {
         // returns the obfuscated strings calls itself.
         if self.is_inside_correct_fn && self.fn_id.is_none() && self.get_obfuscated_strings_fn != fn_decl.ident.to_id() {
             self.fn_id = Some(fn_decl.ident.to_id());
-            fn_decl.take();
+
+            // The index can be mapped before the array is referenced directly,
+            // like `n = n - 0x84; var c = o[n];`, so look for it again
+            if self.index.is_none() {
+                self.is_inside_fn_decl = true;
+                fn_decl.visit_children_with(self);
+                self.is_inside_fn_decl = old_is_inside_fn_decl;
+            }
+
+            let mut encoding_visitor = FindEncodingVisitor::default();
+            fn_decl.visit_children_with(&mut encoding_visitor);
+            self.encoding = encoding_visitor.encoding();
         }
 
         // Reset state
@@ -275,7 +1330,18 @@ impl VisitMut for FindIndexFunctionVisitor {
         }
     }
 
-    fn visit_mut_call_expr(&mut self, call: &mut CallExpr) {
+    fn visit_expr(&mut self, expr: &Expr) {
+        // The string array can be referenced directly instead of being returned by a function
+        if let Expr::Ident(id) = expr {
+            if self.is_inside_fn_decl && id.to_id() == self.get_obfuscated_strings_fn {
+                self.is_inside_correct_fn = true;
+            }
+        }
+
+        expr.visit_children_with(self);
+    }
+
+    fn visit_call_expr(&mut self, call: &CallExpr) {
         // Ignore if we're in the correct function
         if self.is_inside_correct_fn {
             return;
@@ -290,225 +1356,576 @@ impl VisitMut for FindIndexFunctionVisitor {
         }
     }
 
-    fn visit_mut_assign_expr(&mut self, assignment: &mut AssignExpr) {
-        assignment.visit_mut_children_with(self);
-
-        // Skip if we're not inside the correct function, or if we
-        // already got the expression
-        if !self.is_inside_correct_fn || self.index.is_some() {
-            return;
+    fn visit_function(&mut self, function: &Function) {
+        let old_param = self.param.take();
+        if let Some(Pat::Ident(id)) = function.params.first().map(|param| &param.pat) {
+            self.param = Some(id.to_id());
         }
+        function.visit_children_with(self);
+        self.param = old_param;
+    }
 
-        // Skip if the assignment operator isn't "=",
-        // or if the right side of the assignment isn't a BinaryExpression
-        if assignment.op != op!("=") {
+    fn visit_assign_expr(&mut self, assignment: &AssignExpr) {
+        assignment.visit_children_with(self);
+
+        // Skip if we're not inside the correct function, or if we
+        // already got the expression
+        if !self.is_inside_correct_fn || self.index.is_some() {
             return;
         }
 
-        // Is the right side of the assignment a binary expression?
-        if let Expr::Bin(bin) = &*assignment.right {
-            // Is the right side of the binary expression a numeric literal?
-            if let Expr::Lit(Lit::Num(n)) = &*bin.right {
-                self.index = Some(Index {
-                    offset: n.value,
-                    op: bin.op
-                });
-            }
+        // Is the function's first parameter reassigned, like `n = n - 0x84` or `n -= 0x84`?
+        let param = match (assignment.left.as_ident(), &self.param) {
+            (Some(id), Some(param)) if id.to_id() == *param => param.clone(),
+            _ => return
+        };
+        let expr = match assignment.op.to_update() {
+            Some(op) => Expr::Bin(BinExpr {
+                span: assignment.span,
+                op,
+                left: Box::new(Expr::Ident(Ident::from(param.clone()))),
+                right: assignment.right.clone()
+            }),
+            None => (*assignment.right).clone()
+        };
+        if is_wrapper_arg(&expr, std::slice::from_ref(&param)) && uses_ident(&expr, &param) {
+            self.index = Some(Index {
+                param,
+                expr
+            });
         }
     }
 }
 
-/// Finds the answer and expression.
+/// Finds the answer and expression in the function that rotates the obfuscated strings.
+///
+/// The function is called with the function that returns the obfuscated strings and
+/// the answer, and rotates the strings in a loop until the expression equals the answer:
+/// ```js
+/// (function(e, s) {
+///     for (var t = x, n = e(); [];)
+///         try {
+///             var i = parseInt(t(146)) / 1 * (-parseInt(t(132)) / 2) + parseFloat(t(141)) / 3;
+///             if (i === s) break;
+///             n.push(n.shift());
+///         } catch {
+///             n.push(n.shift());
+///         }
+/// })(r, -981043 + -131413 * 5 + 2298101);
+/// ```
+///
+/// The loop can also be `while (!![])` or `for (;;)`, and the expression can be
+/// assigned to a variable declared before the loop.
 struct FindObfExpression {
-    /// The function that returns the obfuscated strings.
+    /// The function that returns the obfuscated strings, or the array if it's indexed directly.
     get_obfuscated_strings_fn: Id,
 
-    /// If we're inside the correct CallExpression.
-    /// This is only used internally.
-    is_inside_correct_call_expr: bool,
-
-    /// The expected answer for the computation.
-    answer: Option<f64>,
-
-    /// The expression used to compute the potential answer.
-    expr: Option<BinExpr>
+    /// The expression used to compute the potential answer and the expected answer,
+    /// or why they couldn't be found.
+    rotation: Option<Result<(Expr, f64), String>>
 }
 
 impl FindObfExpression {
     fn new(get_obfuscated_strings_fn: Id) -> Self {
         Self {
             get_obfuscated_strings_fn,
-            is_inside_correct_call_expr: false,
-            answer: None,
-            expr: None
+            rotation: None
         }
     }
-}
 
-impl VisitMut for FindObfExpression {
-    fn visit_mut_call_expr(&mut self, call_expr: &mut CallExpr) {
-        // Is the first argument the function that returns the obfuscated strings?
-        if let Some(fn_id_arg) = call_expr.args.get(0) {
-            if let Expr::Ident(id) = &*fn_id_arg.expr {
-                if id.to_id() != self.get_obfuscated_strings_fn {
-                    return;
-                }
-            }
-        }
+    /// Gets the expression and answer from a call to the rotation function.
+    fn get_rotation(call_expr: &CallExpr) -> Result<(Expr, f64), String> {
+        // Value to use to check if the obfuscation reverse process has completed.
+        // This might not be simplified yet.
+        let answer = match call_expr.args.get(1).and_then(|arg| eval_arg(&arg.expr, &HashMap::new())) {
+            Some(Value::Num(answer)) => answer,
+            _ => return Err(String::from("Array rotation answer isn't a number"))
+        };
 
-        // Value to use to check if the obfuscation reverse process has completed
-        let answer = if let Some(answer_arg) = call_expr.args.get(1) {
-            if let Expr::Lit(Lit::Num(n)) = &*answer_arg.expr {
-                n.value
-            } else {
-                return;
-            }
-        } else {
-            return;
+        let mut callee = call_expr.callee
+            .as_expr()
+            .ok_or("Array rotation callee isn't a function")?;
+        while let Expr::Paren(paren) = &**callee {
+            callee = &paren.expr;
+        }
+        let function = match &**callee {
+            Expr::Fn(fn_expr) => &fn_expr.function,
+            _ => return Err(String::from("Array rotation callee isn't a function"))
+        };
+        let answer_param = match function.params.get(1).map(|param| &param.pat) {
+            Some(Pat::Ident(id)) => id.to_id(),
+            _ => return Err(String::from("Array rotation function has no answer parameter"))
         };
+        let body = function.body
+            .as_ref()
+            .ok_or("Array rotation function has no body")?;
+
+        // Find the statements inside the loop's try block
+        let loop_body = body.stmts
+            .iter()
+            .find_map(|stmt| match stmt {
+                Stmt::For(for_stmt) => Some(&*for_stmt.body),
+                Stmt::While(while_stmt) => Some(&*while_stmt.body),
+                Stmt::DoWhile(do_while) => Some(&*do_while.body),
+                _ => None
+            })
+            .ok_or("Array rotation loop not found")?;
+        let try_block = match loop_body {
+            Stmt::Try(try_stmt) => &try_stmt.block,
+            Stmt::Block(block) => block.stmts
+                .iter()
+                .find_map(|stmt| match stmt {
+                    Stmt::Try(try_stmt) => Some(&try_stmt.block),
+                    _ => None
+                })
+                .ok_or("Array rotation loop has no try statement")?,
+            _ => return Err(String::from("Array rotation loop has no try statement"))
+        };
+
+        // Find the variable compared against the answer, like `if (i === s) break;`
+        let (check_index, checksum_var) = try_block.stmts
+            .iter()
+            .enumerate()
+            .find_map(|(i, stmt)| Some((i, get_checked_var(stmt, &answer_param)?)))
+            .ok_or("Array rotation answer check not found")?;
+
+        // Find the last value assigned to the variable before it's checked
+        try_block.stmts[..check_index]
+            .iter()
+            .rev()
+            .find_map(|stmt| match stmt {
+                Stmt::Decl(Decl::Var(var)) => var.decls
+                    .iter()
+                    .rev()
+                    .find_map(|declarator| match (&declarator.name, &declarator.init) {
+                        (Pat::Ident(id), Some(init)) if id.to_id() == checksum_var => Some(&**init),
+                        _ => None
+                    }),
+                Stmt::Expr(ExprStmt { expr, .. }) => match &**expr {
+                    Expr::Assign(assign) if assign.op == op!("=") => match assign.left.as_ident() {
+                        Some(id) if id.to_id() == checksum_var => Some(&*assign.right),
+                        _ => None
+                    },
+                    _ => None
+                },
+                _ => None
+            })
+            .map(|expr| (expr.clone(), answer))
+            .ok_or_else(|| String::from("Array rotation expression not found"))
+    }
+}
 
-        self.answer = Some(answer);
-        call_expr.span.take();
+/// Gets the variable `stmt` compares against `answer_param` before breaking out of the loop,
+/// for `if (i === s) break;`, which can also use `==` or have an `else`.
+fn get_checked_var(stmt: &Stmt, answer_param: &Id) -> Option<Id> {
+    let Stmt::If(if_stmt) = stmt else {
+        return None;
+    };
+    let breaks = match &*if_stmt.cons {
+        Stmt::Break(..) => true,
+        Stmt::Block(block) => matches!(block.stmts.as_slice(), [Stmt::Break(..)]),
+        _ => false
+    };
+    let Expr::Bin(bin) = &*if_stmt.test else {
+        return None;
+    };
+    if !breaks || !matches!(bin.op, op!("===") | op!("==")) {
+        return None;
+    }
 
-        // Set state
-        let old_is_inside_correct_call_expr = self.is_inside_correct_call_expr;
-        self.is_inside_correct_call_expr = true;
-        call_expr.visit_mut_children_with(self);
-        self.is_inside_correct_call_expr = old_is_inside_correct_call_expr;
+    match (&*bin.left, &*bin.right) {
+        (Expr::Ident(var), Expr::Ident(param)) | (Expr::Ident(param), Expr::Ident(var)) if param.to_id() == *answer_param => {
+            Some(var.to_id())
+        },
+        _ => None
     }
+}
 
-    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
-        if !self.is_inside_correct_call_expr {
+impl Visit for FindObfExpression {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if self.rotation.is_some() {
             return;
         }
-        declarator.visit_mut_children_with(self);
 
-        if let Some(expr) = &declarator.init {
-            if let Expr::Bin(bin) = &**expr {
-                self.expr = Some(bin.clone());
-            }
+        // Is the first argument the function that returns the obfuscated strings?
+        match expr {
+            Expr::Call(call_expr) if is_rotation_call(call_expr, &self.get_obfuscated_strings_fn) => {
+                self.rotation = Some(Self::get_rotation(call_expr));
+            },
+            _ => expr.visit_children_with(self)
         }
     }
 }
 
-/// Replaces the `parseInt` calls in the expression and the calls to the index function.
-struct ExprVisitor<'strings> {
-    /// The index data.
-    index_data: Index,
+/// If `call` passes `get_obfuscated_strings_fn` as its first argument, like the rotation does.
+fn is_rotation_call(call: &CallExpr, get_obfuscated_strings_fn: &Id) -> bool {
+    call.args.first().is_some_and(|arg| {
+        matches!(&*arg.expr, Expr::Ident(id) if id.to_id() == *get_obfuscated_strings_fn)
+    })
+}
 
-    /// The obfuscated strings.
-    obfuscated_strings: &'strings VecDeque<JsWord>
+/// Removes the call to the rotation function that [FindObfExpression] found,
+/// once the strings are rotated.
+struct RemoveRotationVisitor<'a> {
+    /// The function that returns the obfuscated strings, or the array if it's indexed directly.
+    get_obfuscated_strings_fn: &'a Id,
+
+    /// If the call was removed.
+    removed: bool
 }
 
-impl<'strings> ExprVisitor<'strings> {
-    fn new(index_data: Index, obfuscated_strings: &'strings VecDeque<JsWord>) -> Self {
-        Self {
-            index_data,
-            obfuscated_strings
+impl<'a> VisitMut for RemoveRotationVisitor<'a> {
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        if self.removed {
+            return;
+        }
+
+        match expr {
+            Expr::Call(call_expr) if is_rotation_call(call_expr, self.get_obfuscated_strings_fn) => {
+                expr.take();
+                self.removed = true;
+            },
+            _ => expr.visit_mut_children_with(self)
         }
     }
 }
 
-/// Parses a string as an integer, ignoring non-numeric characters.
-/// This is the equivalent to `parseInt` in JavaScript.
-fn atoi<F: FromStr>(input: &str) -> Result<F, <F as FromStr>::Err> {
-    let i = input
-        .find(|c: char| !c.is_numeric())
-        .unwrap_or_else(|| input.len());
+/// The expression that is compared against the answer to find how many times the strings
+/// array is rotated, compiled so it can be evaluated for every rotation without
+/// touching the AST.
+///
+/// Example:
+/// ```js
+/// parseInt(t(146)) / 1 * (-parseInt(t(132)) / 2) + parseFloat(t(141)) / 3
+/// ```
+#[derive(Debug)]
+enum Checksum {
+    /// A number.
+    Num(f64),
+
+    /// A `parseInt` or `parseFloat` call on the string at `index` before the
+    /// array is rotated, decoded with `key` if the strings are RC4 encoded.
+    Parse {
+        parse: ParseFn,
+        index: usize,
+        key: Option<JsWord>
+    },
+
+    /// A negated expression.
+    Neg(Box<Checksum>),
+
+    /// A binary expression, where `op` is `+`, `-`, `*`, `/` or `%`.
+    Bin(BinaryOp, Box<Checksum>, Box<Checksum>)
+}
+
+/// The function a [Checksum::Parse] term parses its string with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum ParseFn {
+    /// `parseInt` with the given radix, which is `0` if it isn't passed.
+    Int(i32),
 
-    input[..i].parse::<F>()
+    /// `parseFloat`.
+    Float
 }
 
-impl<'strings> VisitMut for ExprVisitor<'strings> {
-    fn visit_mut_expr(&mut self, expr: &mut Expr) {
-        expr.visit_mut_children_with(self);
+impl ParseFn {
+    /// Parses `s` with this function.
+    fn parse(self, s: &str) -> f64 {
+        match self {
+            ParseFn::Int(radix) => parse_int(s, radix),
+            ParseFn::Float => parse_float(s)
+        }
+    }
+}
 
-        if let Expr::Call(call) = expr {
-            // Check if this is a call to parseInt
-            if let Callee::Expr(callee_expr) = &call.callee {
-                if let Expr::Ident(id) = &**callee_expr {
-                    if id.sym.to_string() != "parseInt" {
-                        return;
-                    }
-                } else {
-                    return;
+/// The strings a [Checksum] uses, and the value of every string after parsing.
+type ParsedStrings<'a> = HashMap<(ParseFn, &'a Option<JsWord>), Vec<f64>>;
+
+impl Checksum {
+    /// Compiles `expr`. Returns an error if it uses anything other than numbers,
+    /// arithmetic and `parseInt` or `parseFloat` calls on calls to the index function.
+    fn compile(expr: &Expr, decoder: &Decoder) -> Result<Self, String> {
+        match expr {
+            Expr::Lit(Lit::Num(n)) => Ok(Self::Num(n.value)),
+            Expr::Paren(paren) => Self::compile(&paren.expr, decoder),
+            Expr::Unary(unary) => {
+                let arg = Self::compile(&unary.arg, decoder)?;
+                match unary.op {
+                    UnaryOp::Minus => Ok(Self::Neg(Box::new(arg))),
+                    UnaryOp::Plus => Ok(arg),
+                    op => Err(format!("Unsupported operator `{}` in array rotation expression", op))
                 }
-            } else {
-                return;
+            },
+            Expr::Bin(bin) => match bin.op {
+                op!(bin, "+") | op!(bin, "-") | op!("*") | op!("/") | op!("%") => Ok(Self::Bin(
+                    bin.op,
+                    Box::new(Self::compile(&bin.left, decoder)?),
+                    Box::new(Self::compile(&bin.right, decoder)?)
+                )),
+                op => Err(format!("Unsupported operator `{}` in array rotation expression", op))
+            },
+            Expr::Call(call) => {
+                let parse = match call.callee.as_expr().and_then(|callee| callee.as_ident()) {
+                    Some(callee) if &*callee.sym == "parseInt" => match call.args.get(1) {
+                        Some(radix) => match eval_arg(&radix.expr, &HashMap::new()) {
+                            Some(radix) => ParseFn::Int(to_int32(radix.to_number())),
+                            None => return Err(String::from("Array rotation expression has a parseInt radix that isn't a constant"))
+                        },
+                        None => ParseFn::Int(0)
+                    },
+                    Some(callee) if &*callee.sym == "parseFloat" => ParseFn::Float,
+                    _ => return Err(String::from("Unsupported call in array rotation expression"))
+                };
+                let index_call = match call.args.first().map(|arg| &*arg.expr) {
+                    Some(Expr::Call(index_call)) => index_call,
+                    _ => return Err(String::from("Array rotation expression parses something other than a string"))
+                };
+
+                let term = decoder.index_args(index_call).and_then(|args| {
+                    let index = decoder.index_data.map(args.first().unwrap_or(&Value::Undefined)).ok()?;
+                    let key = match decoder.encoding {
+                        Encoding::Rc4(..) => Some(get_key(&args)?.clone()),
+                        Encoding::Base64(..) | Encoding::None => None
+                    };
+                    Some(Self::Parse { parse, index, key })
+                });
+                // Calls that can't be resolved are NaN, whatever the rotation
+                Ok(term.unwrap_or(Self::Num(f64::NAN)))
+            },
+            _ => Err(String::from("Unsupported expression in array rotation expression"))
+        }
+    }
+
+    /// Adds the functions and keys the strings are parsed and decoded with to `terms`.
+    fn add_terms<'a>(&'a self, terms: &mut HashSet<(ParseFn, &'a Option<JsWord>)>) {
+        match self {
+            Self::Num(..) => {},
+            Self::Parse { parse, key, .. } => {
+                terms.insert((*parse, key));
+            },
+            Self::Neg(arg) => arg.add_terms(terms),
+            Self::Bin(_, left, right) => {
+                left.add_terms(terms);
+                right.add_terms(terms);
             }
+        }
+    }
 
-            // Get call to get_index
-            if let Some(argument) = call.args.get(0) {
-                if let Expr::Call(get_index_call) = &*argument.expr {
-                    // Is a CallExpression
-                    if let Some(offset_arg) = get_index_call.args.get(0) {
-                        if let Expr::Lit(Lit::Num(offset)) = &*offset_arg.expr {
-                            // NaN as a node
-                            let nan = Expr::Ident(
-                                Ident::new(
-                                    JsWord::from("NaN"),
-                                    Span::default()
-                                )
-                            );
-
-                            // Replace node
-                            *expr = match get_index(offset.value as u32, self.index_data.offset as u32, self.index_data.op) {
-                                Some(index) => {
-                                    match self.obfuscated_strings.get(index as usize) {
-                                        Some(s) => match atoi::<usize>(s.to_string().as_str()) {
-                                            Ok(n) => Expr::Lit(Lit::Num(Number::from(n))),
-                                            Err(_) => nan
-                                        },
-                                        None => nan
-                                    }
-                                },
-                                None => nan
-                            };
-                        }
-                    }
+    /// Evaluates the expression with the strings array rotated `rotation` times.
+    fn eval(&self, rotation: usize, values: &ParsedStrings) -> f64 {
+        match self {
+            Self::Num(n) => *n,
+            Self::Parse { parse, index, key } => {
+                let values = &values[&(*parse, key)];
+                if *index < values.len() {
+                    values[(index + rotation) % values.len()]
+                } else {
+                    f64::NAN
+                }
+            },
+            Self::Neg(arg) => -arg.eval(rotation, values),
+            Self::Bin(op, left, right) => {
+                let (l, r) = (left.eval(rotation, values), right.eval(rotation, values));
+                match op {
+                    op!(bin, "+") => l + r,
+                    op!(bin, "-") => l - r,
+                    op!("*") => l * r,
+                    op!("/") => l / r,
+                    op!("%") => l % r,
+                    _ => unreachable!("checked in compile")
                 }
             }
         }
     }
+
+    /// Finds how many times `strings` has to be rotated left for the expression
+    /// to equal `answer`.
+    ///
+    /// Every string is parsed once, so each rotation costs as much as the
+    /// expression, rather than as much as the array.
+    fn solve(&self, strings: &VecDeque<JsWord>, encoding: &Encoding, answer: f64) -> Option<usize> {
+        let mut terms = HashSet::new();
+        self.add_terms(&mut terms);
+        let values: ParsedStrings = terms
+            .into_iter()
+            .map(|(parse, key)| {
+                let values = strings
+                    .iter()
+                    .map(|s| {
+                        let plaintext = match (encoding, key) {
+                            (Encoding::Rc4(alphabet), Some(key)) => decode_rc4(s, key, alphabet),
+                            _ => Some(s.to_string())
+                        };
+                        plaintext.map_or(f64::NAN, |s| parse.parse(&s))
+                    })
+                    .collect();
+                ((parse, key), values)
+            })
+            .collect();
+
+        (0..strings.len()).find(|rotation| self.eval(*rotation, &values) == answer)
+    }
+}
+
+/// Converts a number to a 32-bit integer, following JavaScript's `ToInt32`.
+fn to_int32(n: f64) -> i32 {
+    if !n.is_finite() {
+        return 0;
+    }
+    n.trunc().rem_euclid(4294967296.0) as u32 as i32
+}
+
+/// Parses the integer at the start of a string in base `radix`, ignoring leading whitespace.
+/// This follows JavaScript's `parseInt`, where a `radix` of `0` is the same as not passing one:
+/// the radix is 16 if the string starts with `0x`, or 10 otherwise.
+///
+/// Example: `parseInt(' -0x1Fpx', 0)` is `-31`, `parseInt('12.5', 10)` is `12`
+/// and `parseInt('z', 10)` is `NaN`.
+pub fn parse_int(input: &str, radix: i32) -> f64 {
+    let mut s = input.trim_start_matches(is_js_whitespace);
+    let sign = if s.starts_with('-') { -1.0 } else { 1.0 };
+    if let Some(unsigned) = s.strip_prefix(['+', '-']) {
+        s = unsigned;
+    }
+
+    // `0x` is only a prefix if the radix is 16 or not given
+    let (mut radix, strip_prefix) = match radix {
+        0 => (10, true),
+        2..=36 => (radix as u32, radix == 16),
+        _ => return f64::NAN
+    };
+    if strip_prefix {
+        if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            s = hex;
+            radix = 16;
+        }
+    }
+
+    // Only ASCII digits and letters are digits
+    let end = s.find(|c: char| !c.is_digit(radix)).unwrap_or(s.len());
+    let digits = &s[..end];
+    if digits.is_empty() {
+        return f64::NAN;
+    }
+
+    let value = if radix == 10 {
+        // Rounded correctly, however many digits there are
+        digits.parse().unwrap_or(f64::NAN)
+    } else {
+        // Exact while it fits, then rounded on every digit
+        let mut exact: Option<u128> = Some(0);
+        let mut value = 0.0;
+        for c in digits.chars() {
+            let digit = c.to_digit(radix).expect("checked above");
+            exact = exact
+                .and_then(|n| n.checked_mul(radix as u128))
+                .and_then(|n| n.checked_add(digit as u128));
+            value = match exact {
+                Some(n) => n as f64,
+                None => value * radix as f64 + digit as f64
+            };
+        }
+        value
+    };
+
+    sign * value
+}
+
+/// Parses the decimal number at the start of a string, ignoring leading whitespace.
+/// This is the equivalent to `parseFloat` in JavaScript.
+///
+/// Example: `parseFloat('  12.5e1px')` is `125`, `parseFloat('-.5')` is `-0.5`
+/// and `parseFloat('px')` is `NaN`.
+pub fn parse_float(input: &str) -> f64 {
+    let s = input.trim_start_matches(is_js_whitespace);
+    let unsigned = s.trim_start_matches(['+', '-']);
+    let sign_len = s.len() - unsigned.len();
+    if sign_len > 1 {
+        return f64::NAN;
+    }
+    if unsigned.starts_with("Infinity") {
+        return if s.starts_with('-') { f64::NEG_INFINITY } else { f64::INFINITY };
+    }
+
+    // The longest prefix matching `digits [. digits] [e [sign] digits]`
+    let bytes = unsigned.as_bytes();
+    let digits = |from: usize| bytes[from..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let int_len = digits(0);
+    let mut len = int_len;
+    let mut has_digits = int_len > 0;
+    if bytes.get(len) == Some(&b'.') {
+        let fract_len = digits(len + 1);
+        has_digits |= fract_len > 0;
+        len += 1 + fract_len;
+    }
+    if !has_digits {
+        return f64::NAN;
+    }
+    if matches!(bytes.get(len), Some(b'e' | b'E')) {
+        let sign = matches!(bytes.get(len + 1), Some(b'+' | b'-')) as usize;
+        let exp_len = digits(len + 1 + sign);
+        if exp_len > 0 {
+            len += 1 + sign + exp_len;
+        }
+    }
+
+    s[..sign_len + len].parse().unwrap_or(f64::NAN)
 }
 
 /// Replaces calls to the index function with the plaintext strings and
 /// removes related code to string obfuscation.
 struct CleanupVisitor<'strings> {
-    /// The [Id] of the index function.
-    index_fn_id: Id,
-
-    /// The index data.
-    index_data: Index,
+    /// Resolves calls to the index function.
+    decoder: &'strings Decoder<'strings>,
 
     /// The deobfuscated strings.
-    plaintext_strings: &'strings VecDeque<JsWord>
+    plaintext_strings: &'strings VecDeque<JsWord>,
+
+    /// The calls that couldn't be resolved.
+    unresolved_calls: Vec<UnresolvedCall>,
+
+    /// If we're inside a wrapper, where calls use the wrapper's parameters
+    /// and can't be resolved.
+    /// This is only used internally.
+    is_inside_wrapper: bool
 }
 
 impl<'strings> CleanupVisitor<'strings> {
-    fn new(index_fn_id: Id, index_data: Index, plaintext_strings: &'strings VecDeque<JsWord>) -> Self {
+    fn new(decoder: &'strings Decoder<'strings>, plaintext_strings: &'strings VecDeque<JsWord>) -> Self {
         Self {
-            index_fn_id,
-            index_data,
-            plaintext_strings
+            decoder,
+            plaintext_strings,
+            unresolved_calls: Vec::new(),
+            is_inside_wrapper: false
         }
     }
 }
 
 impl<'strings> VisitMut for CleanupVisitor<'strings> {
+    fn visit_mut_fn_decl(&mut self, fn_decl: &mut FnDecl) {
+        let old_is_inside_wrapper = self.is_inside_wrapper;
+        self.is_inside_wrapper |= self.decoder.wrappers.contains_key(&fn_decl.ident.to_id());
+        fn_decl.visit_mut_children_with(self);
+        self.is_inside_wrapper = old_is_inside_wrapper;
+    }
+
+    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        let old_is_inside_wrapper = self.is_inside_wrapper;
+        if let Pat::Ident(id) = &declarator.name {
+            self.is_inside_wrapper |= self.decoder.wrappers.contains_key(&id.to_id());
+        }
+        declarator.visit_mut_children_with(self);
+        self.is_inside_wrapper = old_is_inside_wrapper;
+    }
+
     fn visit_mut_stmt(&mut self, s: &mut Stmt) {
         s.visit_mut_children_with(self);
 
+        // Remove the rotation, see [RemoveRotationVisitor]
         if let Stmt::Expr(expr_stmt) = s {
             if matches!(&*expr_stmt.expr, Expr::Invalid(..)) {
                 s.take();
             }
-        } else if let Stmt::Decl(Decl::Fn(fn_decl)) = s {
-            if fn_decl.ident.is_dummy() {
-                // Remove FunctionDeclaration's that return obfuscated strings and index
-                // the obfuscated strings
-                s.take();
-            }
         }
     }
 
@@ -535,39 +1952,775 @@ impl<'strings> VisitMut for CleanupVisitor<'strings> {
         expr.visit_mut_children_with(self);
 
         if let Expr::Call(call_expr) = expr {
-            // Remove marked CallExpression's
-            if call_expr.span.is_dummy() {
-                expr.take();
-                return;
-            }
-
             // Replace calls to index function with the plaintext string
             if let Callee::Expr(callee_expr) = &call_expr.callee {
                 if let Expr::Ident(id) = &**callee_expr {
-                    // Does the callee match the index function identifier?
-                    if id.to_id() != self.index_fn_id {
+                    // Is the callee the index function or one of its wrappers?
+                    if !self.decoder.is_decoder(&id.to_id()) {
                         return;
                     }
 
-                    // Get index value
-                    let index = if let Some(arg) = call_expr.args.get(0) {
-                        if let Expr::Lit(Lit::Num(n)) = &*arg.expr {
-                            n.value
-                        } else {
-                            return;
-                        }
-                    } else {
-                        return;
-                    };
-
                     // Replace call with literal value
-                    if let Some(real_index) = get_index(index as u32, self.index_data.offset as u32, self.index_data.op) {
-                        if let Some(plaintext) = self.plaintext_strings.get(real_index as usize) {
-                            *expr = Expr::Lit(Lit::Str(Str::from(plaintext.clone())));
-                        }
+                    match self.decoder.decode_call(call_expr, self.plaintext_strings) {
+                        Ok(plaintext) => *expr = Expr::Lit(Lit::Str(Str::from(plaintext))),
+                        Err(index_error) if !self.is_inside_wrapper => self.unresolved_calls.push(UnresolvedCall {
+                            span: call_expr.span,
+                            callee: id.sym.clone(),
+                            index_error
+                        }),
+                        Err(..) => {}
                     }
                 }
             }
         }
     }
 }
+
+/// Finds the wrappers that are still referenced outside of wrappers,
+/// for example by a call that couldn't be resolved.
+struct WrapperReferencesVisitor<'a> {
+    /// The wrappers of the index function.
+    wrappers: &'a HashMap<Id, Wrapper>,
+
+    /// The wrappers referenced outside of wrappers.
+    referenced: HashSet<Id>
+}
+
+impl<'a> WrapperReferencesVisitor<'a> {
+    fn new(wrappers: &'a HashMap<Id, Wrapper>) -> Self {
+        Self {
+            wrappers,
+            referenced: HashSet::new()
+        }
+    }
+
+    /// The wrappers that are referenced, along with the wrappers they call.
+    fn used_wrappers(self) -> HashSet<Id> {
+        let mut used = HashSet::new();
+        let mut pending: Vec<Id> = self.referenced.into_iter().collect();
+        while let Some(id) = pending.pop() {
+            if let Some(wrapper) = self.wrappers.get(&id) {
+                if used.insert(id) {
+                    pending.push(wrapper.target.clone());
+                }
+            }
+        }
+
+        used
+    }
+}
+
+impl<'a> VisitMut for WrapperReferencesVisitor<'a> {
+    fn visit_mut_fn_decl(&mut self, fn_decl: &mut FnDecl) {
+        // Skip wrappers, references inside them don't count
+        if !self.wrappers.contains_key(&fn_decl.ident.to_id()) {
+            fn_decl.visit_mut_children_with(self);
+        }
+    }
+
+    fn visit_mut_var_declarator(&mut self, declarator: &mut VarDeclarator) {
+        if let Pat::Ident(id) = &declarator.name {
+            if self.wrappers.contains_key(&id.to_id()) {
+                return;
+            }
+        }
+        declarator.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_ident(&mut self, id: &mut Ident) {
+        if self.wrappers.contains_key(&id.to_id()) {
+            self.referenced.insert(id.to_id());
+        }
+    }
+}
+
+/// Removes the declarations of wrappers that are no longer used.
+struct RemoveWrappersVisitor<'a> {
+    /// The wrappers of the index function.
+    wrappers: &'a HashMap<Id, Wrapper>,
+
+    /// The wrappers that are still used and must be kept.
+    used_wrappers: HashSet<Id>
+}
+
+impl<'a> RemoveWrappersVisitor<'a> {
+    fn new(wrappers: &'a HashMap<Id, Wrapper>, used_wrappers: HashSet<Id>) -> Self {
+        Self {
+            wrappers,
+            used_wrappers
+        }
+    }
+
+    /// If `id` is a wrapper that can be removed.
+    fn is_unused_wrapper(&self, id: &Id) -> bool {
+        self.wrappers.contains_key(id) && !self.used_wrappers.contains(id)
+    }
+}
+
+impl<'a> VisitMut for RemoveWrappersVisitor<'a> {
+    fn visit_mut_stmt(&mut self, s: &mut Stmt) {
+        s.visit_mut_children_with(self);
+
+        match s {
+            Stmt::Decl(Decl::Fn(fn_decl)) if self.is_unused_wrapper(&fn_decl.ident.to_id()) => {
+                s.take();
+            },
+            Stmt::Decl(Decl::Var(var)) => {
+                var.decls.retain(|declarator| match &declarator.name {
+                    Pat::Ident(id) => !self.is_unused_wrapper(&id.to_id()),
+                    _ => true
+                });
+                if var.decls.is_empty() {
+                    s.take();
+                }
+            },
+            _ => {}
+        }
+    }
+
+    // Remove empty statements
+    fn visit_mut_stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        stmts.visit_mut_children_with(self);
+
+        stmts.retain(|s| !matches!(s, Stmt::Empty(..)));
+    }
+
+    // Remove empty ModuleItem's
+    fn visit_mut_module_items(&mut self, stmts: &mut Vec<ModuleItem>) {
+        stmts.visit_mut_children_with(self);
+        stmts.retain(|stmt| !matches!(stmt, ModuleItem::Stmt(Stmt::Empty(..))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use swc_core::common::{DUMMY_SP, GLOBALS, Globals};
+    use swc_core::common::errors::Handler;
+    use swc_core::ecma::codegen::{Emitter, Node};
+    use swc_core::ecma::codegen::text_writer::JsWriter;
+    use swc_ecma_transforms::fixer;
+    use super::*;
+    use crate::deobfuscate::{Deobfuscator, Pass};
+
+    /// The challenge from the README with its strings encoded by obfuscator.io's
+    /// base64 string encoding.
+    const BASE64_CHALLENGE: &str = concat!(
+        r#"function(a){function x(e,s){var t=r();return x=function(n,i){n=n-(-8915+2273+3387*2);var c"#,
+        r#"=t[n];if(x.QzXkVb===undefined){var h=function(o){var u="abcdefghijklmnopqrstuvwxyzABCDEFGH"#,
+        r#"IJKLMNOPQRSTUVWXYZ0123456789+/=";var l="",d="";for(var f=0,p,m,g=0;m=o.charAt(g++);~m&&(p="#,
+        r#"f%4?p*64+m:m,f++%4)?l+=String.fromCharCode(255&p>>(-2*f&6)):0){m=u.indexOf(m)}for(var v=0,"#,
+        r#"y=l.length;v<y;v++){d+="%"+("00"+l.charCodeAt(v).toString(16)).slice(-2)}return decodeURIC"#,
+        r#"omponent(d)};x.hGtYyW=h;e=arguments;x.QzXkVb=!![]}var b=t[0],w=n+b,k=e[w];if(!k){c=x.hGtYy"#,
+        r#"W(c);e[w]=c}else{c=k}return c},x(e,s)}(function(e,s){for(var t=x,n=e();[];)try{var i=parse"#,
+        r#"Int(t(146))/1*(-parseInt(t(132))/2)+parseInt(t(141))/3+parseInt(t(135))/4*(parseInt(t(133)"#,
+        r#")/5)+-parseInt(t(139))/6*(parseInt(t(137))/7)+parseInt(t(147))/8*(parseInt(t(142))/9)+pars"#,
+        r#"eInt(t(134))/10+parseInt(t(140))/11*(-parseInt(t(143))/12);if(i===s)break;n.push(n.shift()"#,
+        r#")}catch{n.push(n.shift())}})(r,-981043+-131413*5+2298101);function r(){var e=["BwfYA2vY",""#,
+        r#"A2v5CW","mZeWodK4v21VBNbT","ndq3mdq1nKLrzMvwyq","nKTqB3HJEa","nZCZotvLvfHYu1G","nte4mJCZmf"#,
+        r#"zJCxrYzG","mJi4EgvWEwXH","Bg9Nmxa","odq3BxjjBMfh","ChjVy2vZCW","nJuZntHTsKXIvuy","ndqZm1zm"#,
+        r#"s3jZCG","mJKXmZmXmLnqrLnPtq","ovL0rKrxuG","ntG4Dujiuu5m"];return r=function(){return e},r("#,
+        r#")}return function(){var e=x;return[a+Math[e(136)](a/Math.PI),Object[e(145)](globalThis[e(1"#,
+        r#"38)]||{}),globalThis[e(144)]]}()}"#
+    );
+
+    /// The challenge from the README with its strings encoded by obfuscator.io's
+    /// RC4 string encoding.
+    const RC4_CHALLENGE: &str = concat!(
+        r#"function(a){function x(e,s){var t=r();return x=function(n,i){n=n-(-8915+2273+3387*2);var c"#,
+        r#"=t[n];if(x.JkOpQa===undefined){var g=function(o){var u="abcdefghijklmnopqrstuvwxyzABCDEFGH"#,
+        r#"IJKLMNOPQRSTUVWXYZ0123456789+/=";var l="",d="";for(var f=0,p,m,h=0;m=o.charAt(h++);~m&&(p="#,
+        r#"f%4?p*64+m:m,f++%4)?l+=String.fromCharCode(255&p>>(-2*f&6)):0){m=u.indexOf(m)}for(var v=0,"#,
+        r#"y=l.length;v<y;v++){d+="%"+("00"+l.charCodeAt(v).toString(16)).slice(-2)}return decodeURIC"#,
+        r#"omponent(d)};var q=function(o,w){var z=[],j=0,b,k="";o=g(o);var y;for(y=0;y<256;y++){z[y]="#,
+        r#"y}for(y=0;y<256;y++){j=(j+z[y]+w.charCodeAt(y%w.length))%256;b=z[y];z[y]=z[j];z[j]=b}y=0;j"#,
+        r#"=0;for(var P=0;P<o.length;P++){y=(y+1)%256;j=(j+z[y])%256;b=z[y];z[y]=z[j];z[j]=b;k+=Strin"#,
+        r#"g.fromCharCode(o.charCodeAt(P)^z[(z[y]+z[j])%256])}return k};x.eRtYuI=q;e=arguments;x.JkOp"#,
+        r#"Qa=!![]}var B=t[0],C=n+B,D=e[C];if(!D){if(x.vBnMkL===undefined){x.vBnMkL=!![]}c=x.eRtYuI(c"#,
+        r#",i);e[C]=c}else{c=D}return c},x(e,s)}(function(e,s){for(var t=x,n=e();[];)try{var i=parseI"#,
+        r#"nt(t(146,"(xP1"))/1*(-parseInt(t(132,"^Hb3"))/2)+parseInt(t(141,"B4|s"))/3+parseInt(t(135,"#,
+        r#""m*Wq"))/4*(parseInt(t(133,"pL@e"))/5)+-parseInt(t(139,"Ej[5"))/6*(parseInt(t(137,"Nq%k"))"#,
+        r#"/7)+parseInt(t(147,"Zz!w"))/8*(parseInt(t(142,"iX~o"))/9)+parseInt(t(134,"T0$u"))/10+parse"#,
+        r#"Int(t(140,"wU}g"))/11*(-parseInt(t(143,"Ho7!"))/12);if(i===s)break;n.push(n.shift())}catch"#,
+        r#"{n.push(n.shift())}})(r,-981043+-131413*5+2298101);function r(){var e=["W4hdSMddKmkqoG","o"#,
+        r#"N3cR8oH","WQiXgx/cKZZdRdC2nhHm","W65aWPNcMe/dMKX9bg14wNO","W6rPcCorWP3cGgu","gtlcHwfeDGWIz"#,
+        r#"xBcNG","W4RdKNZdRrFcGCoprSowW6iHW5fq","W6VcGMxdJxNdKHVcQCo1","rMZcG8oQuW","WPJcHmoGWP8vBSo"#,
+        r#"eoGO","WQpcR8odlmoWWQX1","AGi7p3mtWQFcQCkaWPn7","lCoeW70gAmk5W5pcJ03cKq","ic8NCwxdGZXkWR3d"#,
+        r#"OmoqWRRcVG","BCkeeLZcHxFdPW","FmkmqmktWOFdVNb7WPC"];return r=function(){return e},r()}retu"#,
+        r#"rn function(){var e=x;return[a+Math[e(136,"Yv&2")](a/Math.PI),Object[e(145,"a]9Q")](global"#,
+        r#"This[e(138,"c8)R")]||{}),globalThis[e(144,"kR#z")]]}()}"#
+    );
+
+    /// The challenge from the README with its index function calls made through
+    /// obfuscator.io's string array wrappers, including a wrapper that calls another wrapper.
+    const WRAPPER_CHALLENGE: &str = concat!(
+        r#"function(a){function x(e,s){var t=r();return x=function(n,i){n=n-(-8915+2273+3387*2);var c"#,
+        r#"=t[n];return c},x(e,s)}(function(e,s){function W(P,Q){return x(Q- -0x3c,P)}for(var n=e();["#,
+        r#"];)try{var i=parseInt(W("kQ",0x56))/1*(-parseInt(W("kQ",0x48))/2)+parseInt(W("kQ",0x51))/3"#,
+        r#"+parseInt(W("kQ",0x4b))/4*(parseInt(W("kQ",0x49))/5)+-parseInt(W("kQ",0x4f))/6*(parseInt(W"#,
+        r#"("kQ",0x4d))/7)+parseInt(W("kQ",0x57))/8*(parseInt(W("kQ",0x52))/9)+parseInt(W("kQ",0x4a))"#,
+        r#"/10+parseInt(W("kQ",0x50))/11*(-parseInt(W("kQ",0x53))/12);if(i===s)break;n.push(n.shift()"#,
+        r#")}catch{n.push(n.shift())}})(r,-981043+-131413*5+2298101);function r(){var e=["marker","ke"#,
+        r#"ys","310898Wmonpm","4470456IQfeVa","6KPoxcx","77395eTXrSX","5182730Vcqtrf","228xepyla","lo"#,
+        r#"g1p","847mrInaG","process","65358mJLbUF","4433VLKrsr","2913312SPFSiM","9YtFDWR","588uBHQNL"#,
+        r#""];return r=function(){return e},r()}return function(){function U(P,Q){return x(P+0x1e,Q)}"#,
+        r#"var V=function(P,Q,R){return U(R-0x14,Q)};return[a+Math[V(0x7,"jJ",0x7e)](a/Math.PI),Objec"#,
+        r#"t[V(0x7,"jJ",0x87)](globalThis[V(0x7,"jJ",0x80)]||{}),globalThis[V(0x7,"jJ",0x86)]]}()}"#
+    );
+
+    /// The challenge from the README with hexadecimal string indexes, like `t("0x92")`.
+    const HEX_CHALLENGE: &str = concat!(
+        r#"function(a){function x(e,s){var t=r();return x=function(n,i){n=n-(-8915+2273+3387*2);var c"#,
+        r#"=t[n];return c},x(e,s)}(function(e,s){for(var t=x,n=e();[];)try{var i=parseInt(t("0x92"))/"#,
+        r#"1*(-parseInt(t("0x84"))/2)+parseInt(t("0x8d"))/3+parseInt(t("0x87"))/4*(parseInt(t("0x85")"#,
+        r#")/5)+-parseInt(t("0x8b"))/6*(parseInt(t("0x89"))/7)+parseInt(t("0x93"))/8*(parseInt(t("0x8"#,
+        r#"e"))/9)+parseInt(t("0x86"))/10+parseInt(t("0x8c"))/11*(-parseInt(t("0x8f"))/12);if(i===s)b"#,
+        r#"reak;n.push(n.shift())}catch{n.push(n.shift())}})(r,-981043+-131413*5+2298101);function r("#,
+        r#"){var e=["marker","keys","310898Wmonpm","4470456IQfeVa","6KPoxcx","77395eTXrSX","5182730Vc"#,
+        r#"qtrf","228xepyla","log1p","847mrInaG","process","65358mJLbUF","4433VLKrsr","2913312SPFSiM""#,
+        r#","9YtFDWR","588uBHQNL"];return r=function(){return e},r()}return function(){var e=x;return"#,
+        r#"[a+Math[e("0x88")](a/Math.PI),Object[e("0x91")](globalThis[e("0x8a")]||{}),globalThis[e("0"#,
+        r#"x90")]]}()}"#
+    );
+
+    /// The challenge from the README with negative indexes, like `t(-0x2)`.
+    const NEGATIVE_INDEX_CHALLENGE: &str = concat!(
+        r#"function(a){function x(e,s){var t=r();return x=function(n,i){n=n- -0x10;var c=t[n];return "#,
+        r#"c},x(e,s)}(function(e,s){for(var t=x,n=e();[];)try{var i=parseInt(t(-0x2))/1*(-parseInt(t("#,
+        r#"-0x10))/2)+parseInt(t(-0x7))/3+parseInt(t(-0xd))/4*(parseInt(t(-0xf))/5)+-parseInt(t(-0x9)"#,
+        r#")/6*(parseInt(t(-0xb))/7)+parseInt(t(-0x1))/8*(parseInt(t(-0x6))/9)+parseInt(t(-0xe))/10+p"#,
+        r#"arseInt(t(-0x8))/11*(-parseInt(t(-0x5))/12);if(i===s)break;n.push(n.shift())}catch{n.push("#,
+        r#"n.shift())}})(r,-981043+-131413*5+2298101);function r(){var e=["marker","keys","310898Wmon"#,
+        r#"pm","4470456IQfeVa","6KPoxcx","77395eTXrSX","5182730Vcqtrf","228xepyla","log1p","847mrInaG"#,
+        r#"","process","65358mJLbUF","4433VLKrsr","2913312SPFSiM","9YtFDWR","588uBHQNL"];return r=fun"#,
+        r#"ction(){return e},r()}return function(){var e=x;return[a+Math[e(-0xc)](a/Math.PI),Object[e"#,
+        r#"(-0x3)](globalThis[e(-0xa)]||{}),globalThis[e(-0x4)]]}()}"#
+    );
+
+    /// The challenge from the README with a `while (!![])` rotation loop, the expression
+    /// assigned to a variable declared before the loop, and `parseFloat` calls.
+    const LOOP_VARIANT_CHALLENGE: &str = concat!(
+        r#"function(a){function x(e,s){var t=r();return x=function(n,i){n=n-(-8915+2273+3387*2);var c=t"#,
+        r#"[n];return c},x(e,s)}(function(e,s){var t=x,n=e(),i;while(!![]){try{i=parseFloat(t(146))/1*("#,
+        r#"-parseInt(t(132))/2)+parseInt(t(141))/3+parseInt(t(135))/4*(parseFloat(t(133))/5)+-parseInt("#,
+        r#"t(139))/6*(parseInt(t(137))/7)+parseInt(t(147))/8*(parseInt(t(142))/9)+parseInt(t(134))/10+p"#,
+        r#"arseInt(t(140))/11*(-parseInt(t(143))/12);if(s===i){break}else{n.push(n.shift())}}catch(_){n"#,
+        r#".push(n.shift())}}})(r,-981043+-131413*5+2298101);function r(){var e=["marker","keys","31089"#,
+        r#"8Wmonpm","4470456IQfeVa","6KPoxcx","77395eTXrSX","5182730Vcqtrf","228xepyla","log1p","847mrI"#,
+        r#"naG","process","65358mJLbUF","4433VLKrsr","2913312SPFSiM","9YtFDWR","588uBHQNL"];return r=fu"#,
+        r#"nction(){return e},r()}return function(){var e=x;return[a+Math[e(136)](a/Math.PI),Object[e(1"#,
+        r#"45)](globalThis[e(138)]||{}),globalThis[e(144)]]}()}"#
+    );
+
+    /// The passes run in these tests: this pass and the passes it needs, so the
+    /// output doesn't change when other passes are added to [Pass::DEFAULT].
+    const PASSES: [Pass; 4] = [Pass::ExprSimplifier, Pass::Resolver, Pass::ProxyVars, Pass::Strings];
+
+    /// The expected output for the challenges in these tests.
+    const DEOBFUSCATED: &str = r#"(function(a) {
+    return function() {
+        return [
+            a + Math["log1p"](a / Math.PI),
+            Object["keys"](globalThis["process"] || {}),
+            globalThis["marker"]
+        ];
+    }();
+});
+"#;
+
+    /// Deobfuscates the given challenge code with the default passes.
+    fn deobfuscate(code: &str) -> String {
+        Deobfuscator::new()
+            .passes(&PASSES)
+            .deobfuscate(format!("({})", code))
+            .expect("deobfuscate failed")
+            .code
+    }
+
+    /// Runs the passes before this pass over `code`, then lets `edit` change the AST.
+    /// Returns the deobfuscator, whose source map the AST is in, and the AST.
+    fn prepare(code: &str, edit: impl FnOnce(&mut Program)) -> (Deobfuscator, Program) {
+        let deobfuscator = Deobfuscator::new().passes(&PASSES[..PASSES.len() - 1]);
+        let mut program = deobfuscator
+            .deobfuscate(code.to_string())
+            .expect("deobfuscate failed")
+            .program;
+        edit(&mut program);
+        (deobfuscator, program)
+    }
+
+    /// Runs [Visitor] over `program`, without failing on the errors it emits.
+    /// Returns the visitor, the printed result and if any errors were emitted.
+    fn run_visitor(deobfuscator: &Deobfuscator, mut program: Program) -> (Visitor, String, bool) {
+        let handler = Handler::with_emitter_writer(Box::new(std::io::sink()), None);
+        let mut visitor = Visitor::default();
+        GLOBALS.set(&Globals::new(), || {
+            HANDLER.set(&handler, || program.visit_mut_with(&mut visitor));
+            // Add back any parentheses, as the deobfuscator does before printing
+            program.visit_mut_with(&mut fixer(None));
+        });
+
+        let mut buf = Vec::new();
+        let mut emitter = Emitter {
+            cfg: Default::default(),
+            cm: deobfuscator.source_map().clone(),
+            comments: None,
+            wr: JsWriter::new(deobfuscator.source_map().clone(), "\n", &mut buf, None)
+        };
+        program.emit_with(&mut emitter).expect("failed to print program");
+
+        (visitor, String::from_utf8(buf).expect("printed code isn't UTF-8"), handler.has_errors())
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        let alphabet: Vec<char> = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789+/="
+            .chars()
+            .collect();
+
+        assert_eq!(decode_base64("BwfYA2vY", &alphabet).as_deref(), Some("marker"));
+        assert_eq!(decode_base64("A2v5CW==", &alphabet).as_deref(), Some("keys"));
+        assert_eq!(decode_base64("AmoPBgXV", &alphabet).as_deref(), Some("héllo"));
+    }
+
+    #[test]
+    fn test_base64_strings() {
+        assert_eq!(deobfuscate(BASE64_CHALLENGE), DEOBFUSCATED);
+    }
+
+    #[test]
+    fn test_decode_rc4() {
+        let alphabet: Vec<char> = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789+/="
+            .chars()
+            .collect();
+
+        assert_eq!(decode_rc4("W4hdSMddKmkqoG", "kR#z", &alphabet).as_deref(), Some("marker"));
+        assert_ne!(decode_rc4("W4hdSMddKmkqoG", "a]9Q", &alphabet).as_deref(), Some("marker"));
+    }
+
+    #[test]
+    fn test_rc4_strings() {
+        assert_eq!(deobfuscate(RC4_CHALLENGE), DEOBFUSCATED);
+    }
+
+    #[test]
+    fn test_wrapper_strings() {
+        let output = Deobfuscator::new()
+            .passes(&PASSES)
+            .deobfuscate(format!("({})", WRAPPER_CHALLENGE))
+            .expect("deobfuscate failed");
+
+        assert_eq!(output.code, DEOBFUSCATED);
+        // Calls inside the wrappers aren't reported
+        assert!(output.unresolved_calls.is_empty());
+    }
+
+    #[test]
+    fn test_string_to_number() {
+        assert_eq!(string_to_number("0x1a4"), 420.0);
+        assert_eq!(string_to_number(" 305\n"), 305.0);
+        assert_eq!(string_to_number("-12"), -12.0);
+        assert_eq!(string_to_number("1e3"), 1000.0);
+        assert_eq!(string_to_number(""), 0.0);
+        assert_eq!(string_to_number("-Infinity"), f64::NEG_INFINITY);
+        assert!(string_to_number("-0x1a4").is_nan());
+        assert!(string_to_number("12px").is_nan());
+        assert!(string_to_number("inf").is_nan());
+    }
+
+    #[test]
+    fn test_solve_checksum() {
+        // parseInt(x(0)) / 1 * -(parseInt(x(1)) / 2) + parseInt(x(2)) % 7
+        let parse_int = |index| Box::new(Checksum::Parse { parse: ParseFn::Int(0), index, key: None });
+        let checksum = Checksum::Bin(
+            op!(bin, "+"),
+            Box::new(Checksum::Bin(
+                op!("*"),
+                Box::new(Checksum::Bin(op!("/"), parse_int(0), Box::new(Checksum::Num(1.0)))),
+                Box::new(Checksum::Neg(Box::new(Checksum::Bin(op!("/"), parse_int(1), Box::new(Checksum::Num(2.0))))))
+            )),
+            Box::new(Checksum::Bin(op!("%"), parse_int(2), Box::new(Checksum::Num(7.0))))
+        );
+
+        let strings: VecDeque<JsWord> = (0..5000)
+            .map(|i| JsWord::from(format!("{}abc", i)))
+            .collect();
+        // Rotated 3000 times, the strings start with 3000, 3001 and 3002
+        let answer = 3000.0 * -(3001.0 / 2.0) + 3002.0 % 7.0;
+        assert_eq!(checksum.solve(&strings, &Encoding::None, answer), Some(3000));
+        assert_eq!(checksum.solve(&strings, &Encoding::None, 0.5), None);
+    }
+
+    /// `parseInt` conformance cases: the input, the radix and the expected result.
+    const PARSE_INT_CASES: &[(&str, i32, f64)] = &[
+        ("123", 0, 123.0),
+        ("  123abc", 0, 123.0),
+        ("\u{a0}\u{feff}\u{2028}\t42", 0, 42.0),
+        ("-42", 0, -42.0),
+        ("+42", 0, 42.0),
+        ("-0", 0, -0.0),
+        ("12.9", 0, 12.0),
+        ("1e3", 0, 1.0),
+        ("0x1F", 0, 31.0),
+        ("-0xF", 0, -15.0),
+        ("0X1f", 16, 31.0),
+        ("0x1F", 10, 0.0),
+        ("0b11", 0, 0.0),
+        ("ff", 16, 255.0),
+        ("11", 2, 3.0),
+        ("12", 2, 1.0),
+        ("Zz", 36, 1295.0),
+        ("9007199254740993", 0, 9007199254740992.0),
+        ("99999999999999999999999", 0, 1e23),
+        ("ffffffffffffffffffffffffffffffffff", 16, 87112285931760246646623899502532662132736.0),
+        ("", 0, f64::NAN),
+        ("-", 0, f64::NAN),
+        ("0x", 0, f64::NAN),
+        ("Infinity", 0, f64::NAN),
+        ("\u{85}1", 0, f64::NAN),
+        ("\u{661}\u{662}\u{663}", 0, f64::NAN),
+        ("10", 1, f64::NAN),
+        ("10", 37, f64::NAN),
+        ("10", -1, f64::NAN)
+    ];
+
+    /// `parseFloat` conformance cases: the input and the expected result.
+    const PARSE_FLOAT_CASES: &[(&str, f64)] = &[
+        ("2.5", 2.5),
+        ("  2.5abc", 2.5),
+        ("25e-1", 2.5),
+        ("0.025E+2", 2.5),
+        (".5", 0.5),
+        ("-.5", -0.5),
+        ("-0", -0.0),
+        ("5.", 5.0),
+        ("5.e1", 50.0),
+        ("1e", 1.0),
+        ("1e+", 1.0),
+        ("1_000", 1.0),
+        ("0x10", 0.0),
+        ("Infinity", f64::INFINITY),
+        ("-Infinityx", f64::NEG_INFINITY),
+        ("310898Wmonpm", 310898.0),
+        ("infinity", f64::NAN),
+        ("", f64::NAN),
+        (".", f64::NAN),
+        (".e1", f64::NAN),
+        ("+-1", f64::NAN),
+        ("\u{661}\u{66b}\u{665}", f64::NAN)
+    ];
+
+    /// If `a` and `b` are the same number, telling `0` and `-0` apart.
+    fn same_number(a: f64, b: f64) -> bool {
+        a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan())
+    }
+
+    #[test]
+    fn test_parse_int() {
+        for (input, radix, expected) in PARSE_INT_CASES {
+            let actual = parse_int(input, *radix);
+            assert!(same_number(actual, *expected), "parseInt({:?}, {}) is {}, not {}", input, radix, actual, expected);
+        }
+
+        assert_eq!(to_int32(4294967312.0), 16);
+        assert_eq!(to_int32(-1.5), -1);
+        assert_eq!(to_int32(f64::NAN), 0);
+    }
+
+    #[test]
+    fn test_parse_float() {
+        for (input, expected) in PARSE_FLOAT_CASES {
+            let actual = parse_float(input);
+            assert!(same_number(actual, *expected), "parseFloat({:?}) is {}, not {}", input, actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_rotation_loop_variants() {
+        assert_eq!(deobfuscate(LOOP_VARIANT_CHALLENGE), DEOBFUSCATED);
+        assert_eq!(deobfuscate(&LOOP_VARIANT_CHALLENGE.replace("while(!![])", "for(;;)")), DEOBFUSCATED);
+
+        // The answer is computed by the strings pass if it isn't simplified yet
+        let output = Deobfuscator::new()
+            .passes(&PASSES)
+            .disable(Pass::ExprSimplifier)
+            .deobfuscate(format!("({})", LOOP_VARIANT_CHALLENGE))
+            .expect("deobfuscate failed");
+        assert_eq!(output.code, DEOBFUSCATED);
+    }
+
+    #[test]
+    fn test_unrecognised_rotation() {
+        let code = LOOP_VARIANT_CHALLENGE.replace("if(s===i){break}", "if(s!==i){continue}");
+        let error = Deobfuscator::new()
+            .passes(&PASSES)
+            .deobfuscate(format!("({})", code))
+            .err()
+            .expect("deobfuscate succeeded");
+        assert!(error.to_string().contains("Array rotation answer check not found"));
+    }
+
+    #[test]
+    fn test_failed_decoding_keeps_functions() {
+        // The rotation can't be solved, so the getter and index function are still used
+        let code = HEX_CHALLENGE.replace("if(i===s)break;", "if(i!==s)continue;");
+        let (deobfuscator, program) = prepare(&format!("({})", code), |_| {});
+        let (visitor, output, has_errors) = run_visitor(&deobfuscator, program);
+
+        assert!(has_errors);
+        assert!(visitor.string_arrays.is_empty());
+        assert!(output.contains("function x(e, s) {"));
+        assert!(output.contains("function r() {"));
+        assert!(output.contains("(r, 659993);"));
+    }
+
+    #[test]
+    fn test_hex_strings() {
+        assert_eq!(deobfuscate(HEX_CHALLENGE), DEOBFUSCATED);
+    }
+
+    #[test]
+    fn test_negative_index() {
+        assert_eq!(deobfuscate(NEGATIVE_INDEX_CHALLENGE), DEOBFUSCATED);
+    }
+
+    #[test]
+    fn test_index_transforms() {
+        for transform in ["n-=0x84", "n=(n-0x100)+(0x7c)", "n=n-(0x1<<0x7|0x4)"] {
+            let code = HEX_CHALLENGE.replace("n=n-(-8915+2273+3387*2)", transform);
+            assert_eq!(deobfuscate(&code), DEOBFUSCATED, "{}", transform);
+        }
+        // Adding to a string index would concatenate, so these need number indexes
+        for transform in ["n=n+0x10", "n+=0x10", "n=n+-(-0x10)"] {
+            let code = NEGATIVE_INDEX_CHALLENGE.replace("n=n- -0x10", transform);
+            assert_eq!(deobfuscate(&code), DEOBFUSCATED, "{}", transform);
+        }
+
+        // The offset is computed by the strings pass if it isn't simplified yet
+        let output = Deobfuscator::new()
+            .passes(&PASSES)
+            .disable(Pass::ExprSimplifier)
+            .deobfuscate(format!("({})", HEX_CHALLENGE))
+            .expect("deobfuscate failed");
+        assert_eq!(output.code, DEOBFUSCATED);
+    }
+
+    #[test]
+    fn test_index_errors() {
+        let code = HEX_CHALLENGE
+            .replace(r#"globalThis[e("0x8a")]"#, r#"globalThis[e("0x200")]"#)
+            .replace(r#"globalThis[e("0x90")]"#, "globalThis[e(-0x1)]");
+        let output = Deobfuscator::new()
+            .passes(&PASSES)
+            .deobfuscate(format!("({})", code))
+            .expect("deobfuscate failed");
+
+        let errors: Vec<Option<IndexError>> = output.unresolved_calls
+            .iter()
+            .map(|call| call.index_error)
+            .collect();
+        assert_eq!(errors, vec![
+            Some(IndexError::OutOfRange { index: 380, len: 16 }),
+            Some(IndexError::Invalid(-133.0))
+        ]);
+    }
+
+    #[test]
+    fn test_multiple_string_arrays() {
+        // Arrays of strings are only string arrays if they're returned by a function that redefines itself
+        let code = format!(
+            "function d(){{var e=[\"a\",\"b\"];return e}}var f={};var g={};",
+            HEX_CHALLENGE,
+            RC4_CHALLENGE
+        );
+        let output = Deobfuscator::new()
+            .passes(&PASSES)
+            .deobfuscate(code)
+            .expect("deobfuscate failed");
+
+        let function = DEOBFUSCATED
+            .trim_start_matches('(')
+            .trim_end_matches(");\n");
+        assert_eq!(output.code, format!(r#"function d() {{
+    var e = [
+        "a",
+        "b"
+    ];
+    return e;
+}}
+var f = {};
+var g = {};
+"#, function, function));
+
+        // The challenges use the same names in different scopes
+        let strings: Vec<&str> = output.report.string_arrays
+            .iter()
+            .map(|string_array| &*string_array.strings[0])
+            .collect();
+        assert_eq!(strings, vec!["6KPoxcx", "W6rPcCorWP3cGgu"]);
+    }
+
+    #[test]
+    fn test_string_array_shapes() {
+        let start = HEX_CHALLENGE.find("var e=[").expect("array not found");
+        let end = HEX_CHALLENGE.find("];return r=").expect("array not found") + 2;
+        let strings = HEX_CHALLENGE[start + 7..end - 2].replace(r#"",""#, "|");
+        let getter = HEX_CHALLENGE.replace(&HEX_CHALLENGE[start..end], "");
+
+        // Built by splitting a string
+        let split = HEX_CHALLENGE.replace(&HEX_CHALLENGE[start..end], &format!("var e={}.split(\"|\");", strings));
+        assert_eq!(deobfuscate(&split), DEOBFUSCATED);
+        let computed = split.replace(".split(", "[\"split\"](");
+        assert_eq!(deobfuscate(&computed), DEOBFUSCATED);
+
+        // Declared in the challenge's function, outside of the getter
+        let outside = getter.replacen("function(a){", &format!("function(a){{var o={}.split(\"|\");", strings), 1)
+            .replace("return e},r()", "return o},r()");
+        assert_eq!(deobfuscate(&outside), DEOBFUSCATED);
+
+        // Declared at script level, and left alone if it's still used
+        let script = format!("var o={};({});", &HEX_CHALLENGE[start + 6..end - 1], getter.replace("return e},r()", "return o},r()"));
+        let output = Deobfuscator::new()
+            .passes(&PASSES)
+            .deobfuscate(script.clone())
+            .expect("deobfuscate failed");
+        assert_eq!(output.code, DEOBFUSCATED);
+        let output = Deobfuscator::new()
+            .passes(&PASSES)
+            .deobfuscate(format!("{}o.length;", script))
+            .expect("deobfuscate failed");
+        assert!(output.code.starts_with("var o = ["));
+        assert!(output.code.ends_with("});\no.length;\n"));
+
+        // Separators that aren't string literals aren't string arrays
+        let dynamic = split.replace(r#"split("|")"#, r#"split(a)"#);
+        let output = Deobfuscator::new()
+            .passes(&PASSES)
+            .deobfuscate(format!("({})", dynamic))
+            .expect("deobfuscate failed");
+        assert!(output.report.string_arrays.is_empty());
+        assert!(output.code.contains(".split(a);"));
+    }
+
+    #[test]
+    fn test_direct_string_array() {
+        let start = HEX_CHALLENGE.find("function r(){").expect("getter not found");
+        let end = HEX_CHALLENGE.find("r()}return").expect("getter not found") + 4;
+        let array = &HEX_CHALLENGE[start + 19..HEX_CHALLENGE.find("];return r=").expect("array not found") + 1];
+        // The index function and the rotation reference the array instead of the getter
+        let direct = HEX_CHALLENGE.replace(&HEX_CHALLENGE[start..end], "")
+            .replace("var t=r();", "")
+            .replace("var c=t[n]", "var c=o[n]")
+            .replace("n=e();", "n=e;")
+            .replace("})(r,", "})(o,");
+
+        // Declared at script level
+        let output = Deobfuscator::new()
+            .passes(&PASSES)
+            .deobfuscate(format!("var o={};({});", array, direct))
+            .expect("deobfuscate failed");
+        assert_eq!(output.code, DEOBFUSCATED);
+        assert_eq!(output.report.string_arrays[0].array_fn, "o");
+
+        // Built by splitting a string
+        let strings = array[1..array.len() - 1].replace(r#"",""#, "|");
+        let output = Deobfuscator::new()
+            .passes(&PASSES)
+            .deobfuscate(format!("var o={}.split(\"|\");({});", strings, direct))
+            .expect("deobfuscate failed");
+        assert_eq!(output.code, DEOBFUSCATED);
+
+        // Arrays that aren't passed to a rotation function are left alone
+        let unrelated = format!("var o={};o[0];", array);
+        let output = Deobfuscator::new()
+            .passes(&PASSES)
+            .deobfuscate(unrelated)
+            .expect("deobfuscate failed");
+        assert!(output.code.starts_with("var o = ["));
+        assert!(output.report.string_arrays.is_empty());
+    }
+
+    #[test]
+    fn test_array_argument() {
+        // Arrays passed to an IIFE are only string arrays if they're indexed and rotated
+        let code = r#"function f(){var o=["a","b"];(function(x){console.log(x)})(o);}"#;
+        let output = Deobfuscator::new()
+            .passes(&PASSES)
+            .deobfuscate(code.to_string())
+            .expect("deobfuscate failed");
+        assert_eq!(output.code, "function f() {\n    var o = [\n        \"a\",\n        \"b\"\n    ];\n    (function(x) {\n        console.log(x);\n    })(o);\n}\n");
+        assert!(output.report.string_arrays.is_empty());
+    }
+
+    #[test]
+    fn test_split_empty_separator() {
+        /// Gets the string array of the expression statement `code`.
+        fn split(code: &str) -> Option<Result<VecDeque<JsWord>, String>> {
+            let program = Deobfuscator::new()
+                .passes(&[])
+                .deobfuscate(code.to_string())
+                .expect("deobfuscate failed")
+                .program;
+            match &program {
+                Program::Script(script) => match script.body.as_slice() {
+                    [Stmt::Expr(ExprStmt { expr, .. })] => get_string_array(expr),
+                    _ => panic!("not an expression statement")
+                },
+                _ => panic!("not a script")
+            }
+        }
+
+        // Split per UTF-16 code unit
+        assert_eq!(split(r#""a|é".split("");"#), Some(Ok(VecDeque::from([JsWord::from("a"), JsWord::from("|"), JsWord::from("é")]))));
+        // Surrogate pairs would be split in half
+        assert!(split(r#""a😀".split("");"#).is_some_and(|strings| strings.is_err()));
+        assert_eq!(split(r#""a|b".split(s);"#), None);
+        assert_eq!(split(r#""a|b".join("");"#), None);
+    }
+
+    #[test]
+    fn test_synthesized_calls() {
+        // Calls created by other passes have dummy spans, and are kept
+        let (deobfuscator, program) = prepare(&format!("({});g();", HEX_CHALLENGE), |program| {
+            let Program::Script(script) = program else {
+                panic!("not a script");
+            };
+            let Some(Stmt::Expr(ExprStmt { expr, .. })) = script.body.last_mut() else {
+                panic!("not an expression statement");
+            };
+            let Expr::Call(call) = &mut **expr else {
+                panic!("not a call");
+            };
+            call.span = DUMMY_SP;
+        });
+        let (visitor, output, has_errors) = run_visitor(&deobfuscator, program);
+
+        assert!(!has_errors);
+        assert_eq!(output, format!("{}g();\n", DEOBFUSCATED));
+        let expected = Deobfuscator::new()
+            .passes(&PASSES)
+            .deobfuscate(format!("({})", HEX_CHALLENGE))
+            .expect("deobfuscate failed")
+            .report
+            .string_arrays;
+        assert_eq!(visitor.string_arrays.len(), 1);
+        assert_eq!(visitor.string_arrays[0].array_fn, expected[0].array_fn);
+        assert_eq!(visitor.string_arrays[0].index_fn, expected[0].index_fn);
+        assert_eq!(visitor.string_arrays[0].rotations, expected[0].rotations);
+        assert_eq!(visitor.string_arrays[0].strings, expected[0].strings);
+    }
+
+    #[test]
+    fn test_unresolved_calls() {
+        // The index isn't a constant, so this call can't be resolved
+        let code = HEX_CHALLENGE.replace(r#"globalThis[e("0x90")]"#, "globalThis[e(a)]");
+        let output = Deobfuscator::new()
+            .passes(&PASSES)
+            .deobfuscate(format!("({})", code))
+            .expect("deobfuscate failed");
+
+        assert_eq!(output.unresolved_calls.len(), 1);
+        assert_eq!(&*output.unresolved_calls[0].callee, "x");
+        assert_eq!(output.unresolved_calls[0].index_error, None);
+        assert!(output.code.contains("globalThis[x(a)]"));
+    }
+}
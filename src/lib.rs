@@ -1,16 +1,8 @@
 use std::fmt::{Debug, Display, Formatter};
-use std::io::Write;
-use std::sync::Arc;
 use base64::alphabet::STANDARD;
 use base64::Engine;
 use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
 use serde_json::Number;
-use swc::config::IsModule;
-use swc_core::common::{chain, FileName, GLOBALS, Globals, Mark, SourceMap};
-use swc_core::common::errors::{EmitterWriter, Handler};
-use swc_core::ecma::ast::EsVersion;
-use swc_core::ecma::visit::as_folder;
-use swc_ecma_parser::{EsConfig, Syntax};
 
 pub mod deobfuscate;
 mod shared_cursor;
@@ -158,7 +150,7 @@ pub fn generate_token(data: &str) -> Result<String, GenerateTokenError> {
         Number::from_f64(math_answer)
             .map_or(
                 serde_json::Value::Null,
-                |n| serde_json::Value::Number(n)
+                serde_json::Value::Number
             ),
 
         // Object.keys(globalThis.process || {})
@@ -222,83 +214,35 @@ impl From<Vec<String>> for GenerateAnswerError {
     }
 }
 
+impl From<deobfuscate::DeobfuscateError> for GenerateAnswerError {
+    fn from(err: deobfuscate::DeobfuscateError) -> Self {
+        match err {
+            deobfuscate::DeobfuscateError::ParseError(e) => Self::ParseError(e),
+            deobfuscate::DeobfuscateError::TransformErrorParseError(e) => Self::TransformErrorParseError(e),
+            deobfuscate::DeobfuscateError::TransformErrors(errs) => Self::TransformErrors(errs),
+            // Not produced when only computing the answer
+            e @ (deobfuscate::DeobfuscateError::PrintError(..) | deobfuscate::DeobfuscateError::MissingInput) => {
+                Self::TransformErrors(vec![e.to_string()])
+            }
+        }
+    }
+}
+
 /// Generates the answer to the challenge.
 /// The returned `Option` is `None` if the expression couldn't be computed.
 fn generate_answer(input: f64, code: String) -> Result<Option<f64>, GenerateAnswerError> {
-    let cm = Arc::<SourceMap>::default();
-    let err_dst = shared_cursor::SharedCursor::new();
-    let handler = Handler::with_emitter(
-        false,
-        false,
-        Box::new(EmitterWriter::new(
-            Box::new(err_dst.clone()) as Box<dyn Write + Send>,
-            None,
-            true,
-            false
-        ))
-    );
-    let compiler = swc::Compiler::new(cm.clone());
-    let fm = cm.new_source_file(FileName::Custom("input.js".into()), code);
-
-    let mut answer = None;
-    let globals = Globals::new();
-    let mut parse_error = None;
-    GLOBALS.set(&globals, || {
-        // We can't return an error inside a closure, so we use a match instead.
-        let program = match compiler.parse_js(
-            fm,
-            &handler,
-            EsVersion::latest(), // Who knows what version they target, but this works
-            Syntax::Es(EsConfig::default()),
-            IsModule::Bool(false),
-            None
-        ) {
-            Ok(v) => v,
-            Err(e) => {
-                parse_error = Some(e);
-                return;
-            }
-        };
-
-        // Run the transformations.
-        use swc_ecma_transforms::optimization::simplify::expr_simplifier;
-        use swc_ecma_transforms::resolver;
-        // Visitor that computes the value we need
-        let mut math_expr_visitor = deobfuscate::math_expr::Visitor::new(input);
-
-        compiler.transform(&handler, program, true, chain!(
-            // Squash the expressions like 4 + 5 * 2 into constant values
-            expr_simplifier(Mark::new(), Default::default()),
-            // Resolve identifiers to scope-aware values
-            resolver(Mark::new(), Mark::new(), false),
-            // Remove proxy variables
-            as_folder(deobfuscate::proxy_vars::Visitor::default()),
-            // Remove string obfuscation
-            as_folder(deobfuscate::strings::Visitor),
-            // Convert expressions like Math["floor"] to Math.floor
-            as_folder(deobfuscate::computed_member_expr::Visitor),
+    let answer = deobfuscate::Deobfuscator::new()
+        .passes(&[
+            deobfuscate::Pass::ExprSimplifier,
+            deobfuscate::Pass::Resolver,
+            deobfuscate::Pass::ProxyVars,
+            deobfuscate::Pass::Strings,
+            deobfuscate::Pass::ComputedMemberExpr,
             // Compute math expression to a constant value
-            as_folder(&mut math_expr_visitor)
-        ));
-
-        // Set answer
-        answer = math_expr_visitor.answer;
-    });
-    // Return deferred parse error
-    if let Some(e) = parse_error {
-        return Err(GenerateAnswerError::from(e));
-    }
-
-    // Parse emitted errors
-    let errors: Vec<String> = String::from_utf8(err_dst.get_ref().unwrap().clone())?
-        .split("\n")
-        .filter(|s| !s.is_empty())
-        .map(String::from)
-        .collect();
-    // Return error if not empty
-    if !errors.is_empty() {
-        return Err(GenerateAnswerError::from(errors));
-    }
+            deobfuscate::Pass::MathExpr
+        ])
+        .input(input)
+        .answer(code)?;
 
     Ok(answer)
 }
@@ -318,4 +262,142 @@ mod tests {
         // Token on right taken from browser
         assert_eq!(result, "eyJyIjpbMC44NjgwOTMzNDIwMDg1MDAxLFtdLCJtYXJrIl0sInQiOiJleUpoYkdjaU9pSmthWElpTENKbGJtTWlPaUpCTWpVMlIwTk5JbjAuLjIwcDRPdVRxMUNUZGRVdGYuaHEybjBuRUc5cXBnY2VtYTZPVGZrWjR3cXZpMnhKUmppd3VhWGpORkhqLURPUlFsMXJQZVphcUNER2VzX2w1dTk0UFNUemdQcWU3dE1kZnFtSEZ6ZXJGMGk2MnFLOVVXdnUwNFpobWIzdHUyNDV5UnZoZ3Vpd21GZk40S3pUZy5iVFlNcE5leDVyaFA2elJwVlRUbk1nIn0=");
     }
+
+    #[test]
+    fn test_generate_answer() {
+        let challenge = decode_data(TEST_DATA)
+            .expect("decode_data failed");
+        let answer = generate_answer(challenge.input, format!("({})", challenge.code))
+            .expect("generate_answer failed");
+        assert_eq!(answer, Some(0.8680933420085001));
+
+        let error = generate_answer(challenge.input, String::from("(function(a){"))
+            .expect_err("generate_answer succeeded");
+        assert!(matches!(error, GenerateAnswerError::ParseError(..)));
+    }
+
+    #[test]
+    fn test_fixed_point() {
+        let challenge = decode_data(TEST_DATA)
+            .expect("decode_data failed");
+        let output = deobfuscate::Deobfuscator::new()
+            .input(challenge.input)
+            .enable(deobfuscate::Pass::MathExpr)
+            .fixed_point(10)
+            .deobfuscate(format!("({})", challenge.code))
+            .expect("deobfuscate failed");
+        let fixed_point = output.fixed_point
+            .expect("fixed point rounds missing");
+
+        assert!(fixed_point.converged);
+        assert!(fixed_point.rounds[0].changed.contains(&deobfuscate::Pass::Strings));
+        assert!(fixed_point.rounds.last().unwrap().changed.is_empty());
+        assert_eq!(output.answer, Some(0.8680933420085001));
+
+        // Later rounds don't report what the first round found again
+        let report = output.report;
+        assert_eq!(report.string_arrays.len(), 1);
+        let proxies: Vec<(&str, &str)> = report.proxies
+            .iter()
+            .map(|proxy| (&*proxy.name, &*proxy.target))
+            .collect();
+        assert_eq!(proxies, vec![("t", "x"), ("e", "x")]);
+        let log1p = report.members.iter().filter(|member| member.key == "log1p").count();
+        assert_eq!(log1p, 1);
+    }
+
+    #[test]
+    fn test_source_map() {
+        let challenge = decode_data(TEST_DATA)
+            .expect("decode_data failed");
+        let output = deobfuscate::Deobfuscator::new()
+            .emit_source_map("challenge.js")
+            .deobfuscate_expr(challenge.code.clone())
+            .expect("deobfuscate failed");
+        let wrapped = deobfuscate::Deobfuscator::new()
+            .deobfuscate(format!("({})", challenge.code))
+            .expect("deobfuscate failed");
+        assert_eq!(output.code, wrapped.code);
+        assert!(wrapped.source_map.is_none());
+
+        let source_map: serde_json::Value = serde_json::from_str(&output.source_map.expect("source map missing"))
+            .expect("source map isn't JSON");
+        assert_eq!(source_map["version"], 3);
+        assert_eq!(source_map["sources"], serde_json::json!(["challenge.js"]));
+        assert_eq!(source_map["sourcesContent"], serde_json::json!([challenge.code]));
+        // Every line of the output is mapped
+        let mappings = source_map["mappings"].as_str().expect("mappings missing");
+        assert_eq!(mappings.split(';').count(), output.code.lines().count());
+        assert!(mappings.split(';').all(|line| !line.is_empty()));
+
+        // Code moved by the passes maps back to where it was
+        let source_map = swc::sourcemap::SourceMap::from_slice(source_map.to_string().as_bytes())
+            .expect("source map isn't valid");
+        let original = |generated: &str| {
+            let (line, text) = output.code
+                .lines()
+                .enumerate()
+                .find(|(_, text)| text.contains(generated))
+                .expect("generated code not found");
+            let column = text.find(generated).expect("generated code not found");
+            let token = source_map.lookup_token(line as u32, column as u32)
+                .expect("generated code isn't mapped");
+            (token.get_src_line(), token.get_src_col())
+        };
+        // The inlined IIFE's return
+        let column = challenge.code.find("return[a+").expect("return not found");
+        assert_eq!(original("return ["), (0, column as u32));
+        // The decoded string, now a property name, keeps the span of the computed key
+        let column = challenge.code.find("[e(136)]").expect("string not found");
+        assert_eq!(original("log1p"), (0, column as u32));
+    }
+
+    #[test]
+    fn test_deobfuscate_expr_trailing_input() {
+        let output = deobfuscate::Deobfuscator::new()
+            .deobfuscate_expr(String::from("1 + 1 /* comment */"))
+            .expect("deobfuscate failed");
+        assert_eq!(output.code, "2;\n");
+
+        let error = deobfuscate::Deobfuscator::new()
+            .deobfuscate_expr(String::from("1 + 1; evil()"))
+            .err()
+            .expect("deobfuscate succeeded");
+        assert!(matches!(error, deobfuscate::DeobfuscateError::ParseError(..)));
+    }
+
+    #[test]
+    fn test_report() {
+        let challenge = decode_data(TEST_DATA)
+            .expect("decode_data failed");
+        let output = deobfuscate::Deobfuscator::new()
+            .input(challenge.input)
+            .enable(deobfuscate::Pass::MathExpr)
+            .deobfuscate_expr(challenge.code)
+            .expect("deobfuscate failed");
+        let report = output.report;
+
+        assert_eq!(report.string_arrays.len(), 1);
+        let string_array = &report.string_arrays[0];
+        assert_eq!((&*string_array.array_fn, &*string_array.index_fn), ("r", "x"));
+        assert_eq!((string_array.offset, string_array.operator.as_deref()), (Some(132.0), Some("-")));
+        assert_eq!(string_array.rotations, 4);
+        assert_eq!(string_array.strings.len(), 16);
+        assert_eq!(string_array.strings[0], "6KPoxcx");
+
+        let proxies: Vec<(&str, &str)> = report.proxies
+            .iter()
+            .map(|proxy| (&*proxy.name, &*proxy.target))
+            .collect();
+        assert_eq!(proxies, vec![("t", "x"), ("e", "x")]);
+        assert!(report.members.iter().any(|member| member.key == "log1p"));
+
+        assert!(report.math_expression.as_ref().is_some_and(|expr| expr.contains("Math.log1p")));
+        assert_eq!(report.answer, Some(0.8680933420085001));
+
+        let json = serde_json::to_value(&report)
+            .expect("report isn't serialisable");
+        assert_eq!(json["string_arrays"][0]["rotations"], 4);
+        assert_eq!(json["answer"], 0.8680933420085001);
+    }
 }
@@ -14,9 +14,11 @@ impl SharedCursor {
         }
     }
 
-    pub fn get_ref(&self) -> std::io::Result<Vec<u8>> {
-        let lock = self.inner.lock().unwrap();
-        Ok(lock.get_ref().clone())
+    /// Takes the written bytes, leaving the cursor empty.
+    pub fn take(&self) -> Vec<u8> {
+        let mut lock = self.inner.lock().unwrap();
+        lock.set_position(0);
+        std::mem::take(lock.get_mut())
     }
 }
 